@@ -0,0 +1,4189 @@
+use clap::ValueEnum;
+use rand::Rng;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet, VecDeque};
+use std::fmt;
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum QueueKind {
+    BinaryHeap,
+    Bucket,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Generator {
+    Uniform,
+    Noise,
+    Valleys,
+    Maze,
+    Gradient,
+}
+
+#[derive(Copy, Clone)]
+pub struct GeneratorParams {
+    pub generator: Generator,
+    pub scale: f64,
+    pub octaves: u32,
+    pub count: usize,
+    pub corridor: usize,
+    pub angle: f64,
+    pub roughness: f64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum CellWidth {
+    U8,
+    U16,
+    U32,
+}
+
+impl CellWidth {
+    pub fn from_bytes(bytes: usize) -> Option<Self> {
+        match bytes {
+            1 => Some(CellWidth::U8),
+            2 => Some(CellWidth::U16),
+            4 => Some(CellWidth::U32),
+            _ => None,
+        }
+    }
+
+    pub fn bytes(&self) -> usize {
+        match self {
+            CellWidth::U8 => 1,
+            CellWidth::U16 => 2,
+            CellWidth::U32 => 4,
+        }
+    }
+
+    pub fn digits(&self) -> usize {
+        self.bytes() * 2
+    }
+
+    pub fn max_value(&self) -> u32 {
+        match self {
+            CellWidth::U8 => u8::MAX as u32,
+            CellWidth::U16 => u16::MAX as u32,
+            CellWidth::U32 => u32::MAX,
+        }
+    }
+
+    pub fn widen(&self, byte: u8) -> u32 {
+        byte as u32 * (self.max_value() / 0xFF)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Cells {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+impl Cells {
+    pub fn new(cell_width: CellWidth) -> Self {
+        match cell_width {
+            CellWidth::U8 => Cells::U8(Vec::new()),
+            CellWidth::U16 => Cells::U16(Vec::new()),
+            CellWidth::U32 => Cells::U32(Vec::new()),
+        }
+    }
+
+    pub fn from_values(cell_width: CellWidth, values: impl IntoIterator<Item = u32>) -> Self {
+        let values = values.into_iter();
+        match cell_width {
+            CellWidth::U8 => Cells::U8(values.map(|val| val as u8).collect()),
+            CellWidth::U16 => Cells::U16(values.map(|val| val as u16).collect()),
+            CellWidth::U32 => Cells::U32(values.collect()),
+        }
+    }
+
+    pub fn cell_width(&self) -> CellWidth {
+        match self {
+            Cells::U8(_) => CellWidth::U8,
+            Cells::U16(_) => CellWidth::U16,
+            Cells::U32(_) => CellWidth::U32,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Cells::U8(data) => data.len(),
+            Cells::U16(data) => data.len(),
+            Cells::U32(data) => data.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, idx: usize) -> u32 {
+        match self {
+            Cells::U8(data) => data[idx] as u32,
+            Cells::U16(data) => data[idx] as u32,
+            Cells::U32(data) => data[idx],
+        }
+    }
+
+    pub fn set(&mut self, idx: usize, val: u32) {
+        match self {
+            Cells::U8(data) => data[idx] = val as u8,
+            Cells::U16(data) => data[idx] = val as u16,
+            Cells::U32(data) => data[idx] = val,
+        }
+    }
+
+    pub fn push(&mut self, val: u32) {
+        self.extend([val]);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..self.len()).map(|idx| self.get(idx))
+    }
+}
+
+impl Extend<u32> for Cells {
+    fn extend<I: IntoIterator<Item = u32>>(&mut self, values: I) {
+        let values = values.into_iter();
+        match self {
+            Cells::U8(data) => data.extend(values.map(|val| val as u8)),
+            Cells::U16(data) => data.extend(values.map(|val| val as u16)),
+            Cells::U32(data) => data.extend(values),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum MapFormat {
+    Text,
+    Binary,
+    BinaryRle,
+}
+
+#[derive(Debug)]
+pub enum MapError {
+    Io(String),
+    Encoding,
+    Empty,
+    InvalidToken {
+        line: usize,
+        column: usize,
+        token: String,
+    },
+    RaggedRow {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    MixedWidth {
+        line: usize,
+        column: usize,
+        expected: usize,
+        found: usize,
+    },
+    UnevenLayer {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    Binary(String),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::Io(e) => write!(f, "lecture impossible: {}", e),
+            MapError::Encoding => write!(f, "le fichier n'est pas du texte UTF-8"),
+            MapError::Empty => write!(f, "la carte ne contient aucune cellule"),
+            MapError::InvalidToken {
+                line,
+                column,
+                token,
+            } => write!(
+                f,
+                "ligne {}, colonne {}: jeton invalide '{}'",
+                line, column, token
+            ),
+            MapError::RaggedRow {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "ligne {}: {} cellules au lieu de {}",
+                line, found, expected
+            ),
+            MapError::MixedWidth {
+                line,
+                column,
+                expected,
+                found,
+            } => write!(
+                f,
+                "ligne {}, colonne {}: cellule de {} chiffres au lieu de {}",
+                line, column, found, expected
+            ),
+            MapError::UnevenLayer {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "ligne {}: couche de {} lignes au lieu de {}",
+                line, found, expected
+            ),
+            MapError::Binary(e) => write!(f, "carte binaire invalide: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+const TRACE_VERSION: u32 = 1;
+const BINARY_MAGIC: &[u8; 4] = b"HXMP";
+const BINARY_VERSION: u8 = 3;
+const BINARY_HEADER_LEN: usize = 16;
+const BINARY_MAX_CELLS: usize = 1 << 28;
+const FLAG_RLE: u8 = 0x01;
+const FLAG_WALLS: u8 = 0x02;
+const FLAG_SEED: u8 = 0x04;
+const FLAG_LAYERS: u8 = 0x08;
+const LAYER_SEPARATOR: &str = "---";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Topology {
+    Ortho,
+    Diag,
+    HexOddR,
+    HexEvenQ,
+}
+
+#[derive(Clone, Debug)]
+pub enum CostModel {
+    Entered,
+    Slope,
+    Squared,
+    Step(u32),
+    Table(Arc<CostTable>),
+}
+
+#[derive(Debug)]
+pub struct CostTable {
+    weights: Vec<u32>,
+    max: u32,
+}
+
+impl CostModel {
+    fn weight(&self, from: u32, to: u32) -> u64 {
+        match self {
+            CostModel::Entered => to as u64,
+            CostModel::Slope => from.abs_diff(to) as u64,
+            CostModel::Squared => (from.abs_diff(to) as u64).pow(2),
+            CostModel::Step(step) => to as u64 + *step as u64,
+            CostModel::Table(table) => table.weights[from as usize * 256 + to as usize] as u64,
+        }
+    }
+
+    fn max_weight(&self, cell_max: u32) -> u64 {
+        match self {
+            CostModel::Entered | CostModel::Slope => cell_max as u64,
+            CostModel::Squared => (cell_max as u64).pow(2),
+            CostModel::Step(step) => cell_max as u64 + *step as u64,
+            CostModel::Table(table) => table.max as u64,
+        }
+    }
+
+    fn max_entry_weight(&self, to: u32, cell_max: u32) -> u64 {
+        match self {
+            CostModel::Entered | CostModel::Step(_) => self.weight(to, to),
+            CostModel::Slope | CostModel::Squared => {
+                self.weight(0, to).max(self.weight(cell_max, to))
+            }
+            CostModel::Table(table) => (0..256)
+                .map(|from| table.weights[from * 256 + to as usize] as u64)
+                .max()
+                .unwrap_or(0),
+        }
+    }
+
+    fn ignores_source(&self) -> bool {
+        matches!(self, CostModel::Entered | CostModel::Step(_))
+    }
+}
+
+const MAX_EXPR_COST: f64 = 65535.0;
+
+enum Expr {
+    Num(f64),
+    From,
+    To,
+    Neg(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, from: f64, to: f64) -> f64 {
+        match self {
+            Expr::Num(n) => *n,
+            Expr::From => from,
+            Expr::To => to,
+            Expr::Neg(e) => -e.eval(from, to),
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.eval(from, to), b.eval(from, to));
+                match op {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    '/' => a / b,
+                    _ => a.powf(b),
+                }
+            }
+            Expr::Call(name, args) => {
+                let values: Vec<f64> = args.iter().map(|arg| arg.eval(from, to)).collect();
+                match name.as_str() {
+                    "abs" => values[0].abs(),
+                    "sqrt" => values[0].sqrt(),
+                    "min" => values[0].min(values[1]),
+                    _ => values[0].max(values[1]),
+                }
+            }
+        }
+    }
+}
+
+struct ExprParser<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    source: &'a str,
+}
+
+impl ExprParser<'_> {
+    fn parse(source: &str) -> Result<Expr, String> {
+        let mut parser = ExprParser {
+            chars: source.char_indices().peekable(),
+            source,
+        };
+        let expr = parser.sum()?;
+        match parser.peek() {
+            Some((i, c)) => Err(format!("caractère inattendu '{}' en position {}", c, i + 1)),
+            None => Ok(expr),
+        }
+    }
+
+    fn peek(&mut self) -> Option<(usize, char)> {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+        self.chars.peek().copied()
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        self.peek().is_some_and(|(_, c)| c == expected) && self.chars.next().is_some()
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut expr = self.product()?;
+        while let Some((_, op @ ('+' | '-'))) = self.peek() {
+            self.chars.next();
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.product()?));
+        }
+        Ok(expr)
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while let Some((_, op @ ('*' | '/'))) = self.peek() {
+            self.chars.next();
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat('-') {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        let base = self.atom()?;
+        if self.eat('^') {
+            return Ok(Expr::Binary('^', Box::new(base), Box::new(self.unary()?)));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        let Some((start, c)) = self.peek() else {
+            return Err("expression incomplète".to_string());
+        };
+        if self.eat('(') {
+            let expr = self.sum()?;
+            return if self.eat(')') {
+                Ok(expr)
+            } else {
+                Err("parenthèse fermante manquante".to_string())
+            };
+        }
+
+        let mut end = start;
+        while let Some((i, c)) = self
+            .chars
+            .next_if(|&(_, c)| c.is_ascii_alphanumeric() || c == '.' || c == '_')
+        {
+            end = i + c.len_utf8();
+        }
+        let token = &self.source[start..end];
+        if token.is_empty() {
+            return Err(format!(
+                "caractère inattendu '{}' en position {}",
+                c,
+                start + 1
+            ));
+        }
+        if c.is_ascii_digit() || c == '.' {
+            return token
+                .parse()
+                .map(Expr::Num)
+                .map_err(|_| format!("nombre invalide '{}'", token));
+        }
+
+        match token {
+            "from" => return Ok(Expr::From),
+            "to" => return Ok(Expr::To),
+            "abs" | "sqrt" | "min" | "max" => {}
+            _ => return Err(format!("identifiant inconnu '{}'", token)),
+        }
+        if !self.eat('(') {
+            return Err(format!("'(' attendu après {}", token));
+        }
+        let mut args = vec![self.sum()?];
+        while self.eat(',') {
+            args.push(self.sum()?);
+        }
+        if !self.eat(')') {
+            return Err(format!("')' attendu pour {}", token));
+        }
+        let arity = if matches!(token, "min" | "max") { 2 } else { 1 };
+        if args.len() != arity {
+            return Err(format!("{} attend {} argument(s)", token, arity));
+        }
+        Ok(Expr::Call(token.to_string(), args))
+    }
+}
+
+pub fn compile_cost_expr(source: &str) -> Result<CostTable, String> {
+    let expr = ExprParser::parse(source)?;
+    let mut weights = Vec::with_capacity(256 * 256);
+    for from in 0..=255u8 {
+        for to in 0..=255u8 {
+            let value = expr.eval(from as f64, to as f64);
+            if !(0.0..=MAX_EXPR_COST).contains(&value) {
+                return Err(format!(
+                    "coût {} hors de [0, {}] pour from={}, to={}",
+                    value, MAX_EXPR_COST, from, to
+                ));
+            }
+            weights.push(value.round() as u32);
+        }
+    }
+    let max = weights.iter().copied().max().unwrap_or(0);
+    Ok(CostTable { weights, max })
+}
+
+const ORTHO_OFFSETS: [(isize, isize); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+const DIAG_OFFSETS: [(isize, isize); 8] = [
+    (-1, 0),
+    (1, 0),
+    (0, -1),
+    (0, 1),
+    (-1, -1),
+    (1, -1),
+    (-1, 1),
+    (1, 1),
+];
+const HEX_ODD_R_OFFSETS: [[(isize, isize); 6]; 2] = [
+    [(1, 0), (-1, 0), (-1, -1), (0, -1), (-1, 1), (0, 1)],
+    [(1, 0), (-1, 0), (0, -1), (1, -1), (0, 1), (1, 1)],
+];
+const HEX_EVEN_Q_OFFSETS: [[(isize, isize); 6]; 2] = [
+    [(1, 0), (1, 1), (0, -1), (-1, 0), (-1, 1), (0, 1)],
+    [(1, 0), (1, -1), (0, -1), (-1, -1), (-1, 0), (0, 1)],
+];
+
+#[derive(Clone, Debug)]
+pub struct Neighborhood {
+    pub topology: Topology,
+    pub diagonal_cost: f64,
+    pub cost: CostModel,
+    pub wrap: bool,
+}
+
+impl Default for Neighborhood {
+    fn default() -> Self {
+        Neighborhood {
+            topology: Topology::Ortho,
+            diagonal_cost: std::f64::consts::SQRT_2,
+            cost: CostModel::Entered,
+            wrap: false,
+        }
+    }
+}
+
+impl Neighborhood {
+    fn offsets(&self, (x, y): (usize, usize)) -> &'static [(isize, isize)] {
+        match self.topology {
+            Topology::Ortho => &ORTHO_OFFSETS,
+            Topology::Diag => &DIAG_OFFSETS,
+            Topology::HexOddR => &HEX_ODD_R_OFFSETS[y % 2],
+            Topology::HexEvenQ => &HEX_EVEN_Q_OFFSETS[x % 2],
+        }
+    }
+
+    pub fn neighbors<'a>(
+        &self,
+        grid: &'a Grid,
+        position: (usize, usize),
+    ) -> impl Iterator<Item = (usize, usize)> + 'a {
+        let (x, y) = position;
+        let wrap = self.wrap;
+        let rows = grid.layer_height();
+        let base = y - y % rows;
+        let planar = self.offsets(position).iter().filter_map(move |&(dx, dy)| {
+            let (nx, row) = if wrap {
+                (
+                    (x as isize + dx).rem_euclid(grid.width as isize) as usize,
+                    ((y - base) as isize + dy).rem_euclid(rows as isize) as usize,
+                )
+            } else {
+                (
+                    x.checked_add_signed(dx)?,
+                    (y - base).checked_add_signed(dy)?,
+                )
+            };
+            let ny = base + row;
+            (nx < grid.width && row < rows && (nx, ny) != position && !grid.is_wall(nx, ny))
+                .then_some((nx, ny))
+        });
+        let stairs = [y.checked_sub(rows), Some(y + rows)]
+            .into_iter()
+            .filter_map(move |ny| {
+                let ny = ny?;
+                (grid.is_stair(x, y)
+                    && ny < grid.height
+                    && grid.is_stair(x, ny)
+                    && !grid.is_wall(x, ny))
+                .then_some((x, ny))
+            });
+        planar.chain(stairs)
+    }
+
+    pub fn estimate_steps(
+        &self,
+        grid: &Grid,
+        heuristic: Heuristic,
+        from: (usize, usize),
+        to: (usize, usize),
+    ) -> u64 {
+        let rows = grid.layer_height();
+        let (from_base, to_base) = (from.1 - from.1 % rows, to.1 - to.1 % rows);
+        if from_base != to_base && rows % 2 == 1 && self.topology == Topology::HexOddR {
+            return 0;
+        }
+        let to = (to.0, from_base + to.1 - to_base);
+        if !self.wrap {
+            return heuristic.steps(self.topology, from, to);
+        }
+        let from = (from.0 + grid.width, from.1 + rows);
+        (0..3)
+            .flat_map(|kx| (0..3).map(move |ky| (kx, ky)))
+            .map(|(kx, ky)| {
+                let image = (to.0 + kx * grid.width, to.1 + ky * rows);
+                heuristic.steps(self.topology, from, image)
+            })
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub fn is_diagonal(&self, from: (usize, usize), to: (usize, usize)) -> bool {
+        self.topology == Topology::Diag && from.0 != to.0 && from.1 != to.1
+    }
+
+    pub fn step_cost(
+        &self,
+        grid: &Grid,
+        from: (usize, usize),
+        to: (usize, usize),
+        maximize: bool,
+    ) -> u64 {
+        let weight = self.weight(
+            grid,
+            grid.get(from.0, from.1),
+            grid.get(to.0, to.1),
+            maximize,
+        );
+        if self.is_diagonal(from, to) {
+            (weight as f64 * self.diagonal_cost).round() as u64
+        } else {
+            weight
+        }
+    }
+
+    fn weight(&self, grid: &Grid, from: u32, to: u32, maximize: bool) -> u64 {
+        let weight = self.cost.weight(from, to);
+        if maximize {
+            self.cost.max_weight(grid.cell_width().max_value()) - weight
+        } else {
+            weight
+        }
+    }
+
+    fn max_entry_cost(&self, grid: &Grid, to: u32) -> u64 {
+        let max_weight = self
+            .cost
+            .max_entry_weight(to, grid.cell_width().max_value());
+        if self.topology == Topology::Diag {
+            (max_weight as f64 * self.diagonal_cost.max(1.0)).ceil() as u64
+        } else {
+            max_weight
+        }
+    }
+
+    pub fn max_step_cost(&self, cell_width: CellWidth) -> u64 {
+        let max_weight = self.cost.max_weight(cell_width.max_value());
+        if self.topology == Topology::Diag {
+            (max_weight as f64 * self.diagonal_cost.max(1.0)).ceil() as u64
+        } else {
+            max_weight
+        }
+    }
+
+    fn min_step_cost(&self, grid: &Grid, start: (usize, usize), maximize: bool) -> u64 {
+        let start_idx = start.1 * grid.width + start.0;
+        let targets = grid
+            .data
+            .iter()
+            .enumerate()
+            .filter(|&(idx, _)| idx != start_idx && !grid.walls[idx])
+            .map(|(_, val)| val);
+        let min_weight = if self.cost.ignores_source() {
+            targets
+                .map(|to| self.weight(grid, to, to, maximize))
+                .min()
+                .unwrap_or(0)
+        } else if grid.cell_width() == CellWidth::U8 {
+            let mut sources = [false; 256];
+            let mut present = [false; 256];
+            for (idx, val) in grid.data.iter().enumerate() {
+                sources[val as usize] |= !grid.walls[idx];
+            }
+            for to in targets {
+                present[to as usize] = true;
+            }
+            let values = |set: [bool; 256]| (0..256u32).filter(move |&val| set[val as usize]);
+            values(present)
+                .flat_map(|to| {
+                    values(sources).map(move |from| self.weight(grid, from, to, maximize))
+                })
+                .min()
+                .unwrap_or(0)
+        } else {
+            0
+        };
+        if self.topology == Topology::Diag && self.diagonal_cost < 1.0 {
+            (min_weight as f64 * self.diagonal_cost).floor() as u64
+        } else {
+            min_weight
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Heuristic {
+    Zero,
+    Manhattan,
+    Chebyshev,
+    Hex,
+}
+
+impl Heuristic {
+    pub fn is_admissible(&self, topology: Topology) -> bool {
+        match self {
+            Heuristic::Zero | Heuristic::Chebyshev => true,
+            Heuristic::Manhattan => topology == Topology::Ortho,
+            Heuristic::Hex => matches!(topology, Topology::HexOddR | Topology::HexEvenQ),
+        }
+    }
+
+    pub fn steps(&self, topology: Topology, from: (usize, usize), to: (usize, usize)) -> u64 {
+        let dx = from.0.abs_diff(to.0);
+        let dy = from.1.abs_diff(to.1);
+        let steps = match self {
+            Heuristic::Zero => 0,
+            Heuristic::Manhattan => dx + dy,
+            Heuristic::Chebyshev => dx.max(dy),
+            Heuristic::Hex => {
+                let (q1, r1) = hex_axial(topology, from);
+                let (q2, r2) = hex_axial(topology, to);
+                let (dq, dr) = (q1 - q2, r1 - r2);
+                ((dq.abs() + dr.abs() + (dq + dr).abs()) / 2) as usize
+            }
+        };
+        steps as u64
+    }
+}
+
+fn hex_axial(topology: Topology, (x, y): (usize, usize)) -> (isize, isize) {
+    let (x, y) = (x as isize, y as isize);
+    match topology {
+        Topology::HexEvenQ => (x, y - (x + (x & 1)) / 2),
+        _ => (x - (y - (y & 1)) / 2, y),
+    }
+}
+
+#[derive(Clone)]
+pub struct SearchOptions {
+    pub moves: Neighborhood,
+    pub heuristic: Heuristic,
+    pub maximize: bool,
+    pub queue: QueueKind,
+    pub bidirectional: bool,
+    pub max_steps: Option<usize>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            moves: Neighborhood::default(),
+            heuristic: Heuristic::Zero,
+            maximize: false,
+            queue: QueueKind::BinaryHeap,
+            bidirectional: false,
+            max_steps: None,
+        }
+    }
+}
+
+enum Frontier {
+    Heap(BinaryHeap<State>),
+    Buckets(BucketQueue),
+}
+
+impl Frontier {
+    fn new(kind: QueueKind, max_step: u64) -> Self {
+        match kind {
+            QueueKind::BinaryHeap => Frontier::Heap(BinaryHeap::new()),
+            QueueKind::Bucket => Frontier::Buckets(BucketQueue::new(max_step)),
+        }
+    }
+
+    fn push(&mut self, state: State) {
+        match self {
+            Frontier::Heap(heap) => heap.push(state),
+            Frontier::Buckets(buckets) => buckets.push(state),
+        }
+    }
+
+    fn pop(&mut self) -> Option<State> {
+        match self {
+            Frontier::Heap(heap) => heap.pop(),
+            Frontier::Buckets(buckets) => buckets.pop(),
+        }
+    }
+
+    fn peek_estimate(&mut self) -> Option<u64> {
+        match self {
+            Frontier::Heap(heap) => heap.peek().map(|state| state.estimate),
+            Frontier::Buckets(buckets) => buckets.peek_estimate(),
+        }
+    }
+}
+
+pub const MAX_BUCKET_STEP: u64 = 1 << 20;
+
+struct BucketQueue {
+    buckets: Vec<Vec<State>>,
+    cursor: u64,
+    len: usize,
+}
+
+impl BucketQueue {
+    fn new(max_step: u64) -> Self {
+        let count = 2 * max_step as usize + 2;
+        BucketQueue {
+            buckets: (0..count).map(|_| Vec::new()).collect(),
+            cursor: 0,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, state: State) {
+        debug_assert!(state.estimate >= self.cursor);
+        let slot = state.estimate as usize % self.buckets.len();
+        self.buckets[slot].push(state);
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<State> {
+        if self.len == 0 {
+            return None;
+        }
+        loop {
+            let slot = self.cursor as usize % self.buckets.len();
+            if let Some(state) = self.buckets[slot].pop() {
+                self.len -= 1;
+                return Some(state);
+            }
+            self.cursor += 1;
+        }
+    }
+
+    fn peek_estimate(&mut self) -> Option<u64> {
+        if self.len == 0 {
+            return None;
+        }
+        loop {
+            let slot = self.cursor as usize % self.buckets.len();
+            if let Some(state) = self.buckets[slot].last() {
+                return Some(state.estimate);
+            }
+            self.cursor += 1;
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Avoid {
+    cells: HashSet<(usize, usize)>,
+    edges: HashSet<((usize, usize), (usize, usize))>,
+}
+
+impl Avoid {
+    fn blocks(&self, from: (usize, usize), to: (usize, usize)) -> bool {
+        self.cells.contains(&to) || self.edges.contains(&(from, to))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CostField {
+    pub width: usize,
+    pub height: usize,
+    pub layers: usize,
+    pub sources: Vec<(usize, usize)>,
+    pub dist: Vec<Option<u64>>,
+    pub prev: Vec<Option<(usize, usize)>>,
+    pub owner: Vec<Option<usize>>,
+}
+
+impl CostField {
+    pub fn layer_height(&self) -> usize {
+        self.height / self.layers
+    }
+
+    pub fn path_to(&self, (x, y): (usize, usize)) -> Vec<(usize, usize)> {
+        if self.dist[y * self.width + x].is_none() {
+            return vec![];
+        }
+        let mut path = vec![(x, y)];
+        let mut curr = (x, y);
+        while let Some(prev) = self.prev[curr.1 * self.width + curr.0] {
+            path.push(prev);
+            curr = prev;
+        }
+        path.reverse();
+        path
+    }
+
+    pub fn path_from(&self, position: (usize, usize)) -> Vec<(usize, usize)> {
+        let mut path = self.path_to(position);
+        path.reverse();
+        path
+    }
+
+    pub fn region_sizes(&self) -> Vec<usize> {
+        let mut sizes = vec![0; self.sources.len()];
+        for &owner in self.owner.iter().flatten() {
+            sizes[owner] += 1;
+        }
+        sizes
+    }
+}
+
+pub struct Alternative {
+    pub path: Vec<(usize, usize)>,
+    pub cost: u64,
+    pub distinct: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TraceEvent {
+    Search {
+        start: (usize, usize),
+        goal: (usize, usize),
+    },
+    Pop {
+        position: (usize, usize),
+        cost: u64,
+    },
+    Relax {
+        from: (usize, usize),
+        to: (usize, usize),
+        cost: u64,
+    },
+}
+
+pub(crate) struct SearchResult {
+    pub path: Vec<(usize, usize)>,
+    pub expanded: usize,
+    pub waypoints: Vec<usize>,
+    pub exact: bool,
+    pub meetings: Vec<(usize, usize)>,
+}
+
+#[derive(Copy, Clone)]
+pub struct LongestPathBudget {
+    pub max_cells: usize,
+    pub time_limit: Duration,
+}
+
+impl Default for LongestPathBudget {
+    fn default() -> Self {
+        LongestPathBudget {
+            max_cells: 100,
+            time_limit: Duration::from_millis(2000),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+struct State {
+    cost: u64,
+    estimate: u64,
+    position: (usize, usize),
+}
+
+impl Ord for State {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .estimate
+            .cmp(&self.estimate)
+            .then_with(|| self.cost.cmp(&other.cost))
+            .then_with(|| self.position.0.cmp(&other.position.0))
+            .then_with(|| self.position.1.cmp(&other.position.1))
+    }
+}
+
+impl PartialOrd for State {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[derive(Clone)]
+pub struct Grid {
+    pub width: usize,
+    pub height: usize,
+    pub data: Cells,
+    pub walls: Vec<bool>,
+    pub seed: Option<u64>,
+    pub layers: usize,
+    pub stairs: Vec<bool>,
+}
+
+impl Grid {
+    pub fn new(width: usize, height: usize, data: Cells) -> Self {
+        Grid {
+            width,
+            height,
+            walls: vec![false; data.len()],
+            stairs: vec![false; data.len()],
+            data,
+            seed: None,
+            layers: 1,
+        }
+    }
+
+    pub fn cell_width(&self) -> CellWidth {
+        self.data.cell_width()
+    }
+
+    pub fn layer_height(&self) -> usize {
+        self.height / self.layers
+    }
+
+    pub fn position(&self, x: usize, y: usize, layer: usize) -> (usize, usize) {
+        (x, layer * self.layer_height() + y)
+    }
+
+    pub fn label(&self, (x, y): (usize, usize)) -> String {
+        if self.layers > 1 {
+            let rows = self.layer_height();
+            format!("({},{},{})", x, y % rows, y / rows)
+        } else {
+            format!("({},{})", x, y)
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> u32 {
+        self.data.get(y * self.width + x)
+    }
+
+    pub fn shade(&self, x: usize, y: usize) -> u8 {
+        (self.get(x, y) as u64 * 0xFF / self.cell_width().max_value() as u64) as u8
+    }
+
+    pub fn hex(&self, x: usize, y: usize) -> String {
+        format!(
+            "{:0width$X}",
+            self.get(x, y),
+            width = self.cell_width().digits()
+        )
+    }
+
+    pub fn is_wall(&self, x: usize, y: usize) -> bool {
+        self.walls[y * self.width + x]
+    }
+
+    pub fn is_stair(&self, x: usize, y: usize) -> bool {
+        self.stairs[y * self.width + x]
+    }
+
+    pub fn contains(&self, (x, y): (usize, usize)) -> bool {
+        x < self.width && y < self.height
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SolveError {
+    OutOfBounds((usize, usize)),
+    Wall((usize, usize)),
+    InvalidRoute,
+    NoSources,
+    StepBudget {
+        required: usize,
+        limit: usize,
+    },
+    InvalidValue {
+        position: (usize, usize),
+        value: u32,
+    },
+    Unsupported(String),
+    NoPath {
+        expanded: usize,
+    },
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SolveError::OutOfBounds((x, y)) => {
+                write!(f, "coordonnée ({},{}) hors de la grille", x, y)
+            }
+            SolveError::Wall((x, y)) => write!(f, "la cellule ({},{}) est un mur", x, y),
+            SolveError::InvalidRoute => write!(f, "un trajet nécessite un départ et une arrivée"),
+            SolveError::NoSources => write!(f, "aucune cellule source"),
+            SolveError::StepBudget { required, limit } => write!(
+                f,
+                "aucun chemin en {} déplacements maximum (il en faut au moins {})",
+                limit, required
+            ),
+            SolveError::InvalidValue {
+                position: (x, y),
+                value,
+            } => write!(
+                f,
+                "valeur 0x{:X} trop grande pour la cellule ({},{})",
+                value, x, y
+            ),
+            SolveError::Unsupported(reason) => write!(f, "{}", reason),
+            SolveError::NoPath { .. } => write!(f, "aucun chemin trouvé"),
+        }
+    }
+}
+
+impl std::error::Error for SolveError {}
+
+#[derive(Clone, Debug)]
+pub struct SearchStats {
+    pub expanded: usize,
+    pub exact: bool,
+    pub elapsed: Duration,
+    pub waypoints: Vec<usize>,
+    pub meetings: Vec<(usize, usize)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct PathResult {
+    pub cost: u64,
+    pub leg_costs: Vec<u64>,
+    pub cells: Vec<(usize, usize)>,
+    pub stats: SearchStats,
+}
+
+fn path_result(
+    grid: &Grid,
+    moves: &Neighborhood,
+    result: SearchResult,
+    elapsed: Duration,
+) -> Result<PathResult, SolveError> {
+    if result.path.is_empty() {
+        return Err(SolveError::NoPath {
+            expanded: result.expanded,
+        });
+    }
+    Ok(PathResult {
+        cost: path_cost(grid, moves, &result.path),
+        leg_costs: result
+            .waypoints
+            .windows(2)
+            .map(|leg| path_cost(grid, moves, &result.path[leg[0]..=leg[1]]))
+            .collect(),
+        stats: SearchStats {
+            expanded: result.expanded,
+            exact: result.exact,
+            elapsed,
+            waypoints: result.waypoints,
+            meetings: result.meetings,
+        },
+        cells: result.path,
+    })
+}
+
+pub struct Solver<'a> {
+    grid: &'a Grid,
+    options: SearchOptions,
+    budget: LongestPathBudget,
+}
+
+impl<'a> Solver<'a> {
+    pub fn new(
+        grid: &'a Grid,
+        options: SearchOptions,
+        budget: LongestPathBudget,
+    ) -> Result<Self, SolveError> {
+        check_cost_support(&options, grid)?;
+        Ok(Solver {
+            grid,
+            options,
+            budget,
+        })
+    }
+
+    pub fn shortest(&self, route: &[(usize, usize)]) -> Result<PathResult, SolveError> {
+        self.route_search(route, None)
+    }
+
+    pub fn shortest_traced(
+        &self,
+        route: &[(usize, usize)],
+        trace: &mut Vec<TraceEvent>,
+    ) -> Result<PathResult, SolveError> {
+        self.route_search(route, Some(trace))
+    }
+
+    pub fn min_steps(&self, route: &[(usize, usize)]) -> Result<Option<usize>, SolveError> {
+        self.check_route(route)?;
+        Ok(route
+            .windows(2)
+            .map(|leg| min_steps(self.grid, leg[0], leg[1], &self.options.moves))
+            .sum::<Option<usize>>())
+    }
+
+    fn route_search(
+        &self,
+        route: &[(usize, usize)],
+        trace: Option<&mut Vec<TraceEvent>>,
+    ) -> Result<PathResult, SolveError> {
+        self.check_route(route)?;
+        let timer = Instant::now();
+        let result = solve_route(self.grid, route, &self.options, trace);
+        if let Some(limit) = self.options.max_steps
+            && result.path.is_empty()
+            && let Some(required) = self.min_steps(route)?
+            && required > limit
+        {
+            return Err(SolveError::StepBudget { required, limit });
+        }
+        self.finish(result, timer)
+    }
+
+    pub fn longest(&self, route: &[(usize, usize)]) -> Result<PathResult, SolveError> {
+        self.check_route(route)?;
+        let timer = Instant::now();
+        let result = solve_longest_route(self.grid, route, &self.options, self.budget);
+        self.finish(result, timer)
+    }
+
+    pub fn alternatives(
+        &self,
+        start: (usize, usize),
+        goal: (usize, usize),
+        k: usize,
+        min_diff: usize,
+    ) -> Result<(Vec<Alternative>, SearchStats), SolveError> {
+        self.check_route(&[start, goal])?;
+        let timer = Instant::now();
+        let (found, expanded) =
+            k_shortest_paths(self.grid, start, goal, &self.options, k, min_diff);
+        if found.is_empty() {
+            return Err(SolveError::NoPath { expanded });
+        }
+        let stats = SearchStats {
+            expanded,
+            exact: true,
+            elapsed: timer.elapsed(),
+            waypoints: vec![],
+            meetings: vec![],
+        };
+        Ok((found, stats))
+    }
+
+    pub fn cost_field(&self, start: (usize, usize)) -> Result<CostField, SolveError> {
+        self.check_cells(&[start])?;
+        Ok(solve_cost_field(self.grid, start, &self.options))
+    }
+
+    pub fn multi_source(&self, sources: &[(usize, usize)]) -> Result<CostField, SolveError> {
+        self.check_sources(sources)?;
+        Ok(solve_multi_source(self.grid, sources, &self.options))
+    }
+
+    pub fn flow_field(&self, goals: &[(usize, usize)]) -> Result<CostField, SolveError> {
+        self.check_sources(goals)?;
+        Ok(solve_flow_field(self.grid, goals, &self.options))
+    }
+
+    fn check_sources(&self, sources: &[(usize, usize)]) -> Result<(), SolveError> {
+        if sources.is_empty() {
+            return Err(SolveError::NoSources);
+        }
+        self.check_cells(sources)
+    }
+
+    fn check_route(&self, route: &[(usize, usize)]) -> Result<(), SolveError> {
+        if route.len() < 2 {
+            return Err(SolveError::InvalidRoute);
+        }
+        self.check_cells(route)
+    }
+
+    fn check_cells(&self, cells: &[(usize, usize)]) -> Result<(), SolveError> {
+        for &(x, y) in cells {
+            if !self.grid.contains((x, y)) {
+                return Err(SolveError::OutOfBounds((x, y)));
+            }
+            if self.grid.is_wall(x, y) {
+                return Err(SolveError::Wall((x, y)));
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&self, result: SearchResult, timer: Instant) -> Result<PathResult, SolveError> {
+        path_result(self.grid, &self.options.moves, result, timer.elapsed())
+    }
+}
+
+pub fn check_cost_support(options: &SearchOptions, grid: &Grid) -> Result<(), SolveError> {
+    let moves = &options.moves;
+    let unsupported = |reason: String| Err(SolveError::Unsupported(reason));
+    if !options.heuristic.is_admissible(moves.topology) {
+        return unsupported(format!(
+            "heuristique {:?} non admissible pour la topologie {:?}",
+            options.heuristic, moves.topology
+        ));
+    }
+    if matches!(moves.cost, CostModel::Table(_)) && grid.cell_width() != CellWidth::U8 {
+        return unsupported(
+            "le modèle de coût par table n'est supporté que pour les cellules 8 bits".into(),
+        );
+    }
+    if matches!(moves.cost, CostModel::Squared) && grid.cell_width() == CellWidth::U32 {
+        return unsupported(
+            "le modèle de coût quadratique n'est pas supporté pour les cellules 32 bits".into(),
+        );
+    }
+    if moves.wrap && moves.topology == Topology::HexOddR && grid.layer_height() % 2 == 1 {
+        return unsupported(
+            "le bouclage en hex-odd-r nécessite un nombre pair de lignes par couche".into(),
+        );
+    }
+    if moves.wrap && moves.topology == Topology::HexEvenQ && grid.width % 2 == 1 {
+        return unsupported(
+            "le bouclage en hex-even-q nécessite un nombre pair de colonnes".into(),
+        );
+    }
+    if options.queue == QueueKind::Bucket
+        && moves.max_step_cost(grid.cell_width()) > MAX_BUCKET_STEP
+    {
+        return unsupported(format!(
+            "la file à seaux est limitée à des coûts de pas ≤ {}",
+            MAX_BUCKET_STEP
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) fn solve_route(
+    grid: &Grid,
+    route: &[(usize, usize)],
+    opts: &SearchOptions,
+    mut trace: Option<&mut Vec<TraceEvent>>,
+) -> SearchResult {
+    let mut result = SearchResult {
+        path: vec![route[0]],
+        expanded: 0,
+        waypoints: vec![0],
+        exact: true,
+        meetings: vec![],
+    };
+
+    if let Some(limit) = opts.max_steps {
+        return solve_step_limited(grid, route, opts, limit);
+    }
+
+    for leg in route.windows(2) {
+        let leg_result = if opts.bidirectional {
+            solve_bidirectional(grid, leg[0], leg[1], opts)
+        } else {
+            solve_dijkstra_avoiding(
+                grid,
+                leg[0],
+                leg[1],
+                opts,
+                &Avoid::default(),
+                trace.as_deref_mut(),
+            )
+        };
+        result.expanded += leg_result.expanded;
+        result.meetings.extend(leg_result.meetings);
+        if leg_result.path.is_empty() {
+            result.path.clear();
+            result.waypoints.clear();
+            return result;
+        }
+        result.path.extend(&leg_result.path[1..]);
+        result.waypoints.push(result.path.len() - 1);
+    }
+
+    result
+}
+
+pub(crate) fn solve_dijkstra(
+    grid: &Grid,
+    start: (usize, usize),
+    end: (usize, usize),
+    opts: &SearchOptions,
+) -> SearchResult {
+    if let Some(limit) = opts.max_steps {
+        return solve_step_limited(grid, &[start, end], opts, limit);
+    }
+    if opts.bidirectional {
+        return solve_bidirectional(grid, start, end, opts);
+    }
+    solve_dijkstra_avoiding(grid, start, end, opts, &Avoid::default(), None)
+}
+
+fn solve_step_limited(
+    grid: &Grid,
+    route: &[(usize, usize)],
+    opts: &SearchOptions,
+    limit: usize,
+) -> SearchResult {
+    let moves = &opts.moves;
+    let idx = |(x, y): (usize, usize)| y * grid.width + x;
+    let mut result = SearchResult {
+        path: vec![],
+        expanded: 0,
+        waypoints: vec![],
+        exact: true,
+        meetings: vec![],
+    };
+
+    let remaining: Vec<Vec<usize>> = route[1..]
+        .iter()
+        .map(|&target| steps_field(grid, target, moves))
+        .collect();
+    let min_step = moves.min_step_cost(grid, route[0], opts.maximize);
+    let estimate = |position, leg: usize| {
+        min_step * moves.estimate_steps(grid, opts.heuristic, position, route[leg + 1])
+    };
+    let to_go = |position, leg: usize| {
+        (leg..route.len() - 1).try_fold(0usize, |total, later| {
+            let from = if later == leg { position } else { route[later] };
+            let steps = remaining[later][idx(from)];
+            (steps != usize::MAX).then_some(total + steps)
+        })
+    };
+    if to_go(route[0], 0).is_none_or(|steps| steps > limit) {
+        return result;
+    }
+
+    let mut labels = vec![(route[0], 0, usize::MAX, 0)];
+    let mut fewest = vec![vec![usize::MAX; grid.data.len()]; route.len() - 1];
+    let mut heap = BinaryHeap::new();
+    heap.push(Reverse((estimate(route[0], 0), 0u64, 0usize)));
+
+    while let Some(Reverse((_, cost, label))) = heap.pop() {
+        let (position, mut leg, _, taken) = labels[label];
+        if taken >= fewest[leg][idx(position)] {
+            continue;
+        }
+        fewest[leg][idx(position)] = taken;
+        result.expanded += 1;
+
+        if position == route[leg + 1] {
+            leg += 1;
+            if leg == route.len() - 1 {
+                let mut curr = label;
+                while curr != usize::MAX {
+                    result.path.push(labels[curr].0);
+                    curr = labels[curr].2;
+                }
+                result.path.reverse();
+                result.waypoints = vec![0];
+                let mut seen = 1;
+                for (i, &position) in result.path.iter().enumerate().skip(1) {
+                    if seen < route.len() && position == route[seen] {
+                        result.waypoints.push(i);
+                        seen += 1;
+                    }
+                }
+                return result;
+            }
+        }
+
+        for neighbor in moves.neighbors(grid, position) {
+            let next_taken = taken + 1;
+            let fits = to_go(neighbor, leg).is_some_and(|steps| next_taken + steps <= limit);
+            if !fits || next_taken >= fewest[leg][idx(neighbor)] {
+                continue;
+            }
+            let next_cost = cost + moves.step_cost(grid, position, neighbor, opts.maximize);
+            labels.push((neighbor, leg, label, next_taken));
+            heap.push(Reverse((
+                next_cost + estimate(neighbor, leg),
+                next_cost,
+                labels.len() - 1,
+            )));
+        }
+    }
+
+    result
+}
+
+fn steps_field(grid: &Grid, target: (usize, usize), moves: &Neighborhood) -> Vec<usize> {
+    let idx = |(x, y): (usize, usize)| y * grid.width + x;
+    let mut steps = vec![usize::MAX; grid.data.len()];
+    if grid.is_wall(target.0, target.1) {
+        return steps;
+    }
+    let mut queue = VecDeque::from([target]);
+    steps[idx(target)] = 0;
+    while let Some(position) = queue.pop_front() {
+        for neighbor in moves.neighbors(grid, position) {
+            if steps[idx(neighbor)] == usize::MAX {
+                steps[idx(neighbor)] = steps[idx(position)] + 1;
+                queue.push_back(neighbor);
+            }
+        }
+    }
+    steps
+}
+
+pub(crate) fn min_steps(
+    grid: &Grid,
+    start: (usize, usize),
+    goal: (usize, usize),
+    moves: &Neighborhood,
+) -> Option<usize> {
+    let steps = steps_field(grid, goal, moves)[start.1 * grid.width + start.0];
+    (steps != usize::MAX).then_some(steps)
+}
+
+pub fn forbid_values(grid: &mut Grid, ranges: &[(u32, u32)], keep: &[(usize, usize)]) -> usize {
+    let mut forbidden = 0;
+    for (idx, value) in grid.data.iter().enumerate() {
+        let position = (idx % grid.width, idx / grid.width);
+        if !grid.walls[idx]
+            && !keep.contains(&position)
+            && ranges
+                .iter()
+                .any(|&(low, high)| (low..=high).contains(&value))
+        {
+            grid.walls[idx] = true;
+            forbidden += 1;
+        }
+    }
+    forbidden
+}
+
+pub(crate) fn solve_bidirectional(
+    grid: &Grid,
+    start: (usize, usize),
+    end: (usize, usize),
+    opts: &SearchOptions,
+) -> SearchResult {
+    let SearchOptions {
+        ref moves,
+        heuristic,
+        maximize,
+        queue,
+        ..
+    } = *opts;
+    let min_step = moves.min_step_cost(grid, start, maximize);
+    let targets = [end, start];
+    let estimate = |side: usize, position| {
+        min_step * moves.estimate_steps(grid, heuristic, position, targets[side])
+    };
+    let idx = |(x, y): (usize, usize)| y * grid.width + x;
+
+    let mut dist = [
+        vec![u64::MAX; grid.data.len()],
+        vec![u64::MAX; grid.data.len()],
+    ];
+    let mut came_from = [
+        vec![usize::MAX; grid.data.len()],
+        vec![usize::MAX; grid.data.len()],
+    ];
+    let max_step = moves.max_step_cost(grid.cell_width());
+    let mut frontiers = [
+        Frontier::new(queue, max_step),
+        Frontier::new(queue, max_step),
+    ];
+    for (side, origin) in [start, end].into_iter().enumerate() {
+        dist[side][idx(origin)] = 0;
+        frontiers[side].push(State {
+            cost: 0,
+            estimate: estimate(side, origin),
+            position: origin,
+        });
+    }
+
+    let mut best = (start == end).then_some((0, start));
+    let mut expanded = 0;
+
+    while let (Some(forward), Some(backward)) =
+        (frontiers[0].peek_estimate(), frontiers[1].peek_estimate())
+    {
+        let bound = if heuristic == Heuristic::Zero {
+            forward + backward
+        } else {
+            forward.max(backward)
+        };
+        if best.is_some_and(|(cost, _)| bound >= cost) {
+            break;
+        }
+
+        let side = if forward <= backward { 0 } else { 1 };
+        let State { cost, position, .. } = frontiers[side].pop().unwrap();
+        if cost > dist[side][idx(position)] {
+            continue;
+        }
+        expanded += 1;
+
+        for neighbor in moves.neighbors(grid, position) {
+            let step = if side == 0 {
+                moves.step_cost(grid, position, neighbor, maximize)
+            } else {
+                moves.step_cost(grid, neighbor, position, maximize)
+            };
+            let next_cost = cost + step;
+
+            if next_cost < dist[side][idx(neighbor)] {
+                frontiers[side].push(State {
+                    cost: next_cost,
+                    estimate: next_cost + estimate(side, neighbor),
+                    position: neighbor,
+                });
+                dist[side][idx(neighbor)] = next_cost;
+                came_from[side][idx(neighbor)] = idx(position);
+
+                let other = dist[1 - side][idx(neighbor)];
+                if other != u64::MAX && best.is_none_or(|(cost, _)| next_cost + other < cost) {
+                    best = Some((next_cost + other, neighbor));
+                }
+            }
+        }
+    }
+
+    let Some((_, meeting)) = best else {
+        return SearchResult {
+            path: vec![],
+            expanded,
+            waypoints: vec![],
+            exact: true,
+            meetings: vec![],
+        };
+    };
+    let mut path = reconstruct_path(&came_from[0], grid.width, meeting);
+    let mut tail = reconstruct_path(&came_from[1], grid.width, meeting);
+    tail.reverse();
+    path.extend(&tail[1..]);
+    SearchResult {
+        waypoints: vec![0, path.len() - 1],
+        path,
+        expanded,
+        exact: true,
+        meetings: vec![meeting],
+    }
+}
+
+pub(crate) fn solve_dijkstra_avoiding(
+    grid: &Grid,
+    start: (usize, usize),
+    end: (usize, usize),
+    opts: &SearchOptions,
+    avoid: &Avoid,
+    mut trace: Option<&mut Vec<TraceEvent>>,
+) -> SearchResult {
+    let SearchOptions {
+        ref moves,
+        heuristic,
+        maximize,
+        queue,
+        ..
+    } = *opts;
+    let min_step = moves.min_step_cost(grid, start, maximize);
+    let estimate = |position| min_step * moves.estimate_steps(grid, heuristic, position, end);
+    let idx = |(x, y): (usize, usize)| y * grid.width + x;
+
+    let mut dist = vec![u64::MAX; grid.data.len()];
+    let mut came_from = vec![usize::MAX; grid.data.len()];
+    let mut heap = Frontier::new(queue, moves.max_step_cost(grid.cell_width()));
+
+    dist[idx(start)] = 0;
+    if let Some(trace) = trace.as_deref_mut() {
+        trace.push(TraceEvent::Search { start, goal: end });
+    }
+    heap.push(State {
+        cost: 0,
+        estimate: estimate(start),
+        position: start,
+    });
+
+    let mut expanded = 0;
+
+    while let Some(State { cost, position, .. }) = heap.pop() {
+        if let Some(trace) = trace.as_deref_mut()
+            && cost <= dist[idx(position)]
+        {
+            trace.push(TraceEvent::Pop { position, cost });
+        }
+
+        if position == end {
+            let path = reconstruct_path(&came_from, grid.width, end);
+            return SearchResult {
+                waypoints: vec![0, path.len() - 1],
+                path,
+                expanded: expanded + 1,
+                exact: true,
+                meetings: vec![],
+            };
+        }
+
+        if cost > dist[idx(position)] {
+            continue;
+        }
+        expanded += 1;
+
+        for neighbor in moves.neighbors(grid, position) {
+            if avoid.blocks(position, neighbor) {
+                continue;
+            }
+            let next_cost = cost + moves.step_cost(grid, position, neighbor, maximize);
+
+            if next_cost < dist[idx(neighbor)] {
+                heap.push(State {
+                    cost: next_cost,
+                    estimate: next_cost + estimate(neighbor),
+                    position: neighbor,
+                });
+                dist[idx(neighbor)] = next_cost;
+                came_from[idx(neighbor)] = idx(position);
+                if let Some(trace) = trace.as_deref_mut() {
+                    trace.push(TraceEvent::Relax {
+                        from: position,
+                        to: neighbor,
+                        cost: next_cost,
+                    });
+                }
+            }
+        }
+    }
+
+    SearchResult {
+        path: vec![],
+        expanded,
+        waypoints: vec![],
+        exact: true,
+        meetings: vec![],
+    }
+}
+
+pub fn encode_trace(grid: &Grid, events: &[TraceEvent]) -> String {
+    let mut out = format!("TRACE {} {} {}\n", TRACE_VERSION, grid.width, grid.height);
+    for event in events {
+        let line = match *event {
+            TraceEvent::Search { start, goal } => {
+                format!("S {} {} {} {}", start.0, start.1, goal.0, goal.1)
+            }
+            TraceEvent::Pop { position, cost } => {
+                format!("P {} {} {}", position.0, position.1, cost)
+            }
+            TraceEvent::Relax { from, to, cost } => {
+                format!("R {} {} {} {} {}", from.0, from.1, to.0, to.1, cost)
+            }
+        };
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+pub fn decode_trace(content: &str, grid: &Grid) -> Result<Vec<TraceEvent>, String> {
+    let mut lines = content.lines().enumerate();
+    let header: Vec<&str> = lines
+        .next()
+        .map(|(_, line)| line.split_whitespace().collect())
+        .unwrap_or_default();
+    if header.len() != 4 || header[0] != "TRACE" {
+        return Err("en-tête TRACE manquant".to_string());
+    }
+    if header[1] != TRACE_VERSION.to_string() {
+        return Err(format!("version {} non supportée", header[1]));
+    }
+    if header[2] != grid.width.to_string() || header[3] != grid.height.to_string() {
+        return Err(format!(
+            "trace {}x{} incompatible avec la grille {}x{}",
+            header[2], header[3], grid.width, grid.height
+        ));
+    }
+
+    let mut events = Vec::new();
+    for (i, line) in lines {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.is_empty() {
+            continue;
+        }
+        let invalid = || format!("ligne {}: événement invalide '{}'", i + 1, line.trim());
+        let numbers = fields[1..]
+            .iter()
+            .map(|f| f.parse::<usize>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid())?;
+        let coord = |at: usize| {
+            let position = (numbers[at], numbers[at + 1]);
+            if grid.contains(position) {
+                Ok(position)
+            } else {
+                Err(format!(
+                    "ligne {}: ({},{}) hors de la grille",
+                    i + 1,
+                    position.0,
+                    position.1
+                ))
+            }
+        };
+        let cost = |at: usize| u64::try_from(numbers[at]).map_err(|_| invalid());
+        let event = match (fields[0], numbers.len()) {
+            ("S", 4) => TraceEvent::Search {
+                start: coord(0)?,
+                goal: coord(2)?,
+            },
+            ("P", 3) => TraceEvent::Pop {
+                position: coord(0)?,
+                cost: cost(2)?,
+            },
+            ("R", 5) => TraceEvent::Relax {
+                from: coord(0)?,
+                to: coord(2)?,
+                cost: cost(4)?,
+            },
+            _ => return Err(invalid()),
+        };
+        events.push(event);
+    }
+    Ok(events)
+}
+
+pub(crate) fn solve_cost_field(
+    grid: &Grid,
+    start: (usize, usize),
+    opts: &SearchOptions,
+) -> CostField {
+    expand_field(grid, &[start], opts, false)
+}
+
+pub(crate) fn solve_multi_source(
+    grid: &Grid,
+    sources: &[(usize, usize)],
+    opts: &SearchOptions,
+) -> CostField {
+    expand_field(grid, sources, opts, false)
+}
+
+pub(crate) fn solve_flow_field(
+    grid: &Grid,
+    goals: &[(usize, usize)],
+    opts: &SearchOptions,
+) -> CostField {
+    expand_field(grid, goals, opts, true)
+}
+
+fn expand_field(
+    grid: &Grid,
+    sources: &[(usize, usize)],
+    opts: &SearchOptions,
+    toward_sources: bool,
+) -> CostField {
+    let moves = &opts.moves;
+    let idx = |(x, y): (usize, usize)| y * grid.width + x;
+    let mut field = CostField {
+        width: grid.width,
+        height: grid.height,
+        layers: grid.layers,
+        sources: sources.to_vec(),
+        dist: vec![None; grid.data.len()],
+        prev: vec![None; grid.data.len()],
+        owner: vec![None; grid.data.len()],
+    };
+    let mut heap = Frontier::new(opts.queue, moves.max_step_cost(grid.cell_width()));
+
+    for (label, &source) in sources.iter().enumerate() {
+        if field.dist[idx(source)].is_some() {
+            continue;
+        }
+        field.dist[idx(source)] = Some(0);
+        field.owner[idx(source)] = Some(label);
+        heap.push(State {
+            cost: 0,
+            estimate: 0,
+            position: source,
+        });
+    }
+
+    while let Some(State { cost, position, .. }) = heap.pop() {
+        if cost > field.dist[idx(position)].unwrap_or(u64::MAX) {
+            continue;
+        }
+
+        for neighbor in moves.neighbors(grid, position) {
+            let step = if toward_sources {
+                moves.step_cost(grid, neighbor, position, opts.maximize)
+            } else {
+                moves.step_cost(grid, position, neighbor, opts.maximize)
+            };
+            let next_cost = cost + step;
+
+            if next_cost < field.dist[idx(neighbor)].unwrap_or(u64::MAX) {
+                heap.push(State {
+                    cost: next_cost,
+                    estimate: next_cost,
+                    position: neighbor,
+                });
+                field.dist[idx(neighbor)] = Some(next_cost);
+                field.prev[idx(neighbor)] = Some(position);
+                field.owner[idx(neighbor)] = field.owner[idx(position)];
+            }
+        }
+    }
+
+    field
+}
+
+pub fn flow_arrow(
+    field: &CostField,
+    topology: Topology,
+    from: (usize, usize),
+    to: (usize, usize),
+) -> char {
+    let rows = field.layer_height();
+    if from.1 / rows != to.1 / rows {
+        return if to.1 < from.1 { '⇑' } else { '⇓' };
+    }
+    let unwrap = |from: usize, to: usize, size: usize| {
+        let (from, to, size) = (from as isize, to as isize, size as isize);
+        match to - from {
+            delta if delta > 1 => to - size,
+            delta if delta < -1 => to + size,
+            _ => to,
+        }
+    };
+    let base = (from.1 - from.1 % rows) as isize;
+    let to = (
+        unwrap(from.0, to.0, field.width),
+        base + unwrap(from.1 % rows, to.1 % rows, rows),
+    );
+    let visual = |(x, y): (isize, isize)| match topology {
+        Topology::Ortho | Topology::Diag => (2 * x, 2 * y),
+        Topology::HexOddR => (2 * x + y.rem_euclid(2), 2 * y),
+        Topology::HexEvenQ => (2 * x, 2 * y + 1 - x.rem_euclid(2)),
+    };
+    let (fx, fy) = visual((from.0 as isize, from.1 as isize));
+    let (tx, ty) = visual(to);
+    match ((tx - fx).signum(), (ty - fy).signum()) {
+        (-1, 0) => '←',
+        (1, 0) => '→',
+        (0, -1) => '↑',
+        (0, 1) => '↓',
+        (-1, -1) => '↖',
+        (1, -1) => '↗',
+        (-1, 1) => '↙',
+        (1, 1) => '↘',
+        _ => '*',
+    }
+}
+
+fn push_layer_separator(content: &mut String, field: &CostField, y: usize) {
+    if y > 0 && y.is_multiple_of(field.layer_height()) {
+        content.push_str(LAYER_SEPARATOR);
+        content.push('\n');
+    }
+}
+
+fn json_sources(field: &CostField) -> String {
+    match field.sources[..] {
+        [start] => format!("\"start\": {}", json_coord(start)),
+        _ => {
+            let coords: Vec<String> = field.sources.iter().map(|&c| json_coord(c)).collect();
+            format!("\"sources\": [{}]", coords.join(", "))
+        }
+    }
+}
+
+fn json_rows<T>(field: &CostField, cells: &[T], cell: impl Fn(&T) -> String) -> String {
+    let rows: Vec<String> = cells
+        .chunks(field.width)
+        .map(|row| {
+            let cells: Vec<String> = row.iter().map(&cell).collect();
+            format!("[{}]", cells.join(", "))
+        })
+        .collect();
+    rows.join(",\n  ")
+}
+
+pub fn encode_flow_field(field: &CostField, topology: Topology, json: bool) -> String {
+    if json {
+        let goals: Vec<String> = field.sources.iter().map(|&c| json_coord(c)).collect();
+        return format!(
+            "{{\"width\": {}, \"height\": {}, \"topology\": \"{}\", \"goals\": [{}], \"next\": [\n  {}\n], \"cost\": [\n  {}\n]}}\n",
+            field.width,
+            field.height,
+            topology.to_possible_value().unwrap().get_name(),
+            goals.join(", "),
+            json_rows(field, &field.prev, |next| next
+                .map_or("null".to_string(), json_coord)),
+            json_rows(field, &field.dist, |cost| cost
+                .map_or("null".to_string(), |c| c.to_string()))
+        );
+    }
+
+    let mut content = String::new();
+    for y in 0..field.height {
+        push_layer_separator(&mut content, field, y);
+        for x in 0..field.width {
+            let idx = y * field.width + x;
+            let symbol = match (field.dist[idx], field.prev[idx]) {
+                (None, _) => 'X',
+                (Some(_), None) => '*',
+                (Some(_), Some(next)) => flow_arrow(field, topology, (x, y), next),
+            };
+            content.push(symbol);
+            content.push(' ');
+        }
+        content.push('\n');
+    }
+    content
+}
+
+pub fn encode_regions(field: &CostField, json: bool) -> String {
+    if json {
+        return format!(
+            "{{\"width\": {}, \"height\": {}, {}, \"region\": [\n  {}\n], \"cost\": [\n  {}\n]}}\n",
+            field.width,
+            field.height,
+            json_sources(field),
+            json_rows(field, &field.owner, |owner| owner
+                .map_or("null".to_string(), |o| o.to_string())),
+            json_rows(field, &field.dist, |cost| cost
+                .map_or("null".to_string(), |c| c.to_string()))
+        );
+    }
+
+    let digits = field.sources.len().saturating_sub(1).to_string().len();
+    let mut content = String::new();
+    for (y, row) in field.owner.chunks(field.width).enumerate() {
+        push_layer_separator(&mut content, field, y);
+        for owner in row {
+            match owner {
+                Some(o) => content.push_str(&format!("{:>width$} ", o, width = digits)),
+                None => content.push_str(&format!("{:->width$} ", "", width = digits)),
+            }
+        }
+        content.push('\n');
+    }
+    content
+}
+
+pub fn encode_cost_field(field: &CostField, json: bool) -> String {
+    if json {
+        return format!(
+            "{{\"width\": {}, \"height\": {}, {}, \"cost\": [\n  {}\n]}}\n",
+            field.width,
+            field.height,
+            json_sources(field),
+            json_rows(field, &field.dist, |cost| cost
+                .map_or("null".to_string(), |c| c.to_string()))
+        );
+    }
+
+    let max = field.dist.iter().flatten().max().copied().unwrap_or(0);
+    let cell_width = [CellWidth::U8, CellWidth::U16]
+        .into_iter()
+        .find(|width| max <= width.max_value() as u64)
+        .unwrap_or(CellWidth::U32);
+    let data = field
+        .dist
+        .iter()
+        .map(|cost| cost.map_or(0, |c| c.min(u32::MAX as u64) as u32));
+    let mut grid = Grid::new(
+        field.width,
+        field.height,
+        Cells::from_values(cell_width, data),
+    );
+    grid.layers = field.layers;
+    grid.walls = field.dist.iter().map(Option::is_none).collect();
+    encode_text_map(&grid)
+}
+
+pub fn json_coord((x, y): (usize, usize)) -> String {
+    format!("[{}, {}]", x, y)
+}
+
+pub fn encode_pred_field(field: &CostField, json: bool) -> String {
+    if json {
+        return format!(
+            "{{\"width\": {}, \"height\": {}, {}, \"predecessor\": [\n  {}\n]}}\n",
+            field.width,
+            field.height,
+            json_sources(field),
+            json_rows(field, &field.prev, |prev| prev
+                .map_or("null".to_string(), json_coord))
+        );
+    }
+
+    let mut content = String::new();
+    for (y, row) in field.prev.chunks(field.width).enumerate() {
+        push_layer_separator(&mut content, field, y);
+        let cells: Vec<String> = row
+            .iter()
+            .map(|prev| prev.map_or("-".to_string(), |(x, y)| format!("{},{}", x, y)))
+            .collect();
+        content.push_str(&cells.join(" "));
+        content.push('\n');
+    }
+    content
+}
+
+pub(crate) fn k_shortest_paths(
+    grid: &Grid,
+    start: (usize, usize),
+    goal: (usize, usize),
+    opts: &SearchOptions,
+    k: usize,
+    min_diff: usize,
+) -> (Vec<Alternative>, usize) {
+    let moves = &opts.moves;
+    let first = solve_dijkstra(grid, start, goal, opts);
+    let mut expanded = first.expanded;
+    if first.path.is_empty() {
+        return (vec![], expanded);
+    }
+
+    let mut found: Vec<Vec<(usize, usize)>> = vec![first.path];
+    let mut candidates: Vec<(u64, Vec<(usize, usize)>)> = Vec::new();
+    let mut accepted = vec![Alternative {
+        cost: path_cost(grid, moves, &found[0]),
+        distinct: found[0].len(),
+        path: found[0].clone(),
+    }];
+    let max_rounds = k.saturating_mul(50);
+
+    while accepted.len() < k && found.len() < max_rounds {
+        let previous = found.last().unwrap().clone();
+        for i in 0..previous.len() - 1 {
+            let spur = previous[i];
+            let root = &previous[..=i];
+            let mut avoid = Avoid::default();
+            for path in &found {
+                if path.len() > i + 1 && &path[..=i] == root {
+                    avoid.edges.insert((path[i], path[i + 1]));
+                }
+            }
+            avoid.cells.extend(&root[..i]);
+
+            let spur_result = solve_dijkstra_avoiding(grid, spur, goal, opts, &avoid, None);
+            expanded += spur_result.expanded;
+            if spur_result.path.is_empty() {
+                continue;
+            }
+            let mut candidate = root[..i].to_vec();
+            candidate.extend(spur_result.path);
+            if !found.contains(&candidate) && !candidates.iter().any(|(_, c)| *c == candidate) {
+                candidates.push((path_cost(grid, moves, &candidate), candidate));
+            }
+        }
+
+        let Some(best) = candidates
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, (cost, path))| (*cost, path.len()))
+            .map(|(idx, _)| idx)
+        else {
+            break;
+        };
+        let (cost, path) = candidates.swap_remove(best);
+
+        let cells: HashSet<(usize, usize)> = path.iter().copied().collect();
+        let distinct = accepted
+            .iter()
+            .map(|alt| cells.iter().filter(|c| !alt.path.contains(c)).count())
+            .min()
+            .unwrap_or(cells.len());
+        if distinct >= min_diff {
+            accepted.push(Alternative {
+                path: path.clone(),
+                cost,
+                distinct,
+            });
+        }
+        found.push(path);
+    }
+
+    (accepted, expanded)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CellUpdate {
+    pub position: (usize, usize),
+    pub value: Option<u32>,
+}
+
+type PlannerEntry = Reverse<((u128, u128), (usize, usize))>;
+
+pub struct IncrementalPlanner {
+    grid: Grid,
+    options: SearchOptions,
+    start: (usize, usize),
+    goal: (usize, usize),
+    g: Vec<u128>,
+    rhs: Vec<u128>,
+    open: BinaryHeap<PlannerEntry>,
+    min_step: u64,
+}
+
+impl IncrementalPlanner {
+    pub fn new(
+        grid: Grid,
+        start: (usize, usize),
+        goal: (usize, usize),
+        options: SearchOptions,
+    ) -> Result<Self, SolveError> {
+        check_cost_support(&options, &grid)?;
+        for (x, y) in [start, goal] {
+            if !grid.contains((x, y)) {
+                return Err(SolveError::OutOfBounds((x, y)));
+            }
+            if grid.is_wall(x, y) {
+                return Err(SolveError::Wall((x, y)));
+            }
+        }
+
+        let min_step = options.moves.min_step_cost(&grid, start, false);
+        let size = grid.data.len();
+        let mut planner = IncrementalPlanner {
+            grid,
+            options,
+            start,
+            goal,
+            g: vec![u128::MAX; size],
+            rhs: vec![u128::MAX; size],
+            open: BinaryHeap::new(),
+            min_step,
+        };
+        let start_idx = planner.idx(start);
+        planner.rhs[start_idx] = 0;
+        planner.open.push(Reverse((planner.key(start), start)));
+        Ok(planner)
+    }
+
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    pub fn apply(&mut self, updates: &[CellUpdate]) -> Result<(), SolveError> {
+        for update in updates {
+            let (x, y) = update.position;
+            if !self.grid.contains((x, y)) {
+                return Err(SolveError::OutOfBounds((x, y)));
+            }
+            match update.value {
+                None if (x, y) == self.start || (x, y) == self.goal => {
+                    return Err(SolveError::Wall((x, y)));
+                }
+                Some(value) if value > self.grid.cell_width().max_value() => {
+                    return Err(SolveError::InvalidValue {
+                        position: (x, y),
+                        value,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        for update in updates {
+            let idx = self.idx(update.position);
+            match update.value {
+                Some(value) => {
+                    self.grid.data.set(idx, value);
+                    self.grid.walls[idx] = false;
+                }
+                None => self.grid.walls[idx] = true,
+            }
+        }
+
+        let min_step = self
+            .options
+            .moves
+            .min_step_cost(&self.grid, self.start, false);
+        if min_step < self.min_step {
+            self.min_step = min_step;
+            let inconsistent: Vec<PlannerEntry> = (0..self.g.len())
+                .filter(|&idx| self.g[idx] != self.rhs[idx])
+                .map(|idx| {
+                    let position = (idx % self.grid.width, idx / self.grid.width);
+                    Reverse((self.key(position), position))
+                })
+                .collect();
+            self.open = inconsistent.into();
+        }
+
+        for update in updates {
+            self.update_vertex(update.position);
+            let neighbors: Vec<(usize, usize)> = self
+                .options
+                .moves
+                .neighbors(&self.grid, update.position)
+                .collect();
+            for neighbor in neighbors {
+                self.update_vertex(neighbor);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn plan(&mut self) -> Result<PathResult, SolveError> {
+        let timer = Instant::now();
+        let mut expanded = 0;
+        while let Some(&Reverse((key, position))) = self.open.peek() {
+            let idx = self.idx(position);
+            if self.g[idx] == self.rhs[idx] || key != self.key(position) {
+                self.open.pop();
+                continue;
+            }
+            let goal_idx = self.idx(self.goal);
+            if key >= self.key(self.goal) && self.g[goal_idx] == self.rhs[goal_idx] {
+                break;
+            }
+
+            self.open.pop();
+            expanded += 1;
+            if self.g[idx] > self.rhs[idx] {
+                self.g[idx] = self.rhs[idx];
+            } else {
+                self.g[idx] = u128::MAX;
+                self.update_vertex(position);
+            }
+            let neighbors: Vec<(usize, usize)> =
+                self.options.moves.neighbors(&self.grid, position).collect();
+            for neighbor in neighbors {
+                self.update_vertex(neighbor);
+            }
+        }
+
+        let path = self.extract_path();
+        let result = SearchResult {
+            waypoints: if path.is_empty() {
+                vec![]
+            } else {
+                vec![0, path.len() - 1]
+            },
+            path,
+            expanded,
+            exact: true,
+            meetings: vec![],
+        };
+        path_result(&self.grid, &self.options.moves, result, timer.elapsed())
+    }
+
+    fn idx(&self, (x, y): (usize, usize)) -> usize {
+        y * self.grid.width + x
+    }
+
+    fn scale(&self) -> u128 {
+        self.g.len() as u128 + 1
+    }
+
+    fn weight(&self, from: (usize, usize), to: (usize, usize)) -> u128 {
+        let cost = self.options.moves.step_cost(&self.grid, from, to, false);
+        cost as u128 * self.scale() + 1
+    }
+
+    fn key(&self, position: (usize, usize)) -> (u128, u128) {
+        let idx = self.idx(position);
+        let best = self.g[idx].min(self.rhs[idx]);
+        let steps = self.options.moves.estimate_steps(
+            &self.grid,
+            self.options.heuristic,
+            position,
+            self.goal,
+        );
+        let estimate = (self.min_step * steps) as u128 * self.scale();
+        (best.saturating_add(estimate), best)
+    }
+
+    fn best_predecessor(&self, position: (usize, usize)) -> Option<(u128, (usize, usize))> {
+        self.options
+            .moves
+            .neighbors(&self.grid, position)
+            .filter(|&prev| self.g[self.idx(prev)] != u128::MAX)
+            .map(|prev| (self.g[self.idx(prev)] + self.weight(prev, position), prev))
+            .min()
+    }
+
+    fn update_vertex(&mut self, position: (usize, usize)) {
+        let idx = self.idx(position);
+        if position != self.start {
+            self.rhs[idx] = if self.grid.is_wall(position.0, position.1) {
+                u128::MAX
+            } else {
+                self.best_predecessor(position)
+                    .map_or(u128::MAX, |(cost, _)| cost)
+            };
+        }
+        if self.g[idx] != self.rhs[idx] {
+            self.open.push(Reverse((self.key(position), position)));
+        }
+    }
+
+    fn extract_path(&self) -> Vec<(usize, usize)> {
+        if self.g[self.idx(self.goal)] == u128::MAX {
+            return vec![];
+        }
+        let mut path = vec![self.goal];
+        let mut curr = self.goal;
+        while curr != self.start {
+            match self.best_predecessor(curr) {
+                Some((cost, prev)) if cost == self.g[self.idx(curr)] => {
+                    path.push(prev);
+                    curr = prev;
+                }
+                _ => return vec![],
+            }
+        }
+        path.reverse();
+        path
+    }
+}
+
+pub fn parse_updates(content: &str, grid: &Grid) -> Result<Vec<Vec<CellUpdate>>, String> {
+    let mut batches = vec![vec![]];
+    for (i, line) in content.lines().enumerate() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.is_empty() {
+            if !batches[batches.len() - 1].is_empty() {
+                batches.push(vec![]);
+            }
+            continue;
+        }
+        let invalid = || format!("ligne {}: mise à jour invalide '{}'", i + 1, line.trim());
+        if fields.len() != 3 {
+            return Err(invalid());
+        }
+        let x = fields[0].parse::<usize>().map_err(|_| invalid())?;
+        let y = fields[1].parse::<usize>().map_err(|_| invalid())?;
+        if !grid.contains((x, y)) {
+            return Err(format!("ligne {}: ({},{}) hors de la grille", i + 1, x, y));
+        }
+        let value = if fields[2].chars().all(|c| c == 'X') {
+            None
+        } else {
+            let value = u32::from_str_radix(fields[2], 16).map_err(|_| invalid())?;
+            if value > grid.cell_width().max_value() {
+                return Err(format!(
+                    "ligne {}: valeur 0x{:X} trop grande pour des cellules de {} octet(s)",
+                    i + 1,
+                    value,
+                    grid.cell_width().bytes()
+                ));
+            }
+            Some(value)
+        };
+        let last = batches.len() - 1;
+        batches[last].push(CellUpdate {
+            position: (x, y),
+            value,
+        });
+    }
+    if batches[batches.len() - 1].is_empty() {
+        batches.pop();
+    }
+    Ok(batches)
+}
+
+pub(crate) fn solve_longest_route(
+    grid: &Grid,
+    route: &[(usize, usize)],
+    opts: &SearchOptions,
+    budget: LongestPathBudget,
+) -> SearchResult {
+    let mut work = grid.clone();
+    let mut result = SearchResult {
+        path: vec![route[0]],
+        expanded: 0,
+        waypoints: vec![0],
+        exact: route.len() == 2,
+        meetings: vec![],
+    };
+
+    for (i, leg) in route.windows(2).enumerate() {
+        let reserved: Vec<usize> = route[i + 2..]
+            .iter()
+            .filter(|&&position| position != leg[1])
+            .map(|&(x, y)| y * work.width + x)
+            .filter(|&idx| !work.walls[idx])
+            .collect();
+        for &idx in &reserved {
+            work.walls[idx] = true;
+        }
+        let keeps_later_legs = |path: &[(usize, usize)]| {
+            let mut probe = work.clone();
+            let wall_path = |probe: &mut Grid, path: &[(usize, usize)]| {
+                for &(x, y) in &path[..path.len() - 1] {
+                    probe.walls[y * probe.width + x] = true;
+                }
+            };
+            wall_path(&mut probe, path);
+            route[i + 1..].windows(2).all(|leg| {
+                let goal = leg[1].1 * probe.width + leg[1].0;
+                if reserved.contains(&goal) {
+                    probe.walls[goal] = false;
+                }
+                let next = solve_dijkstra(&probe, leg[0], leg[1], opts);
+                if next.path.is_empty() {
+                    return false;
+                }
+                wall_path(&mut probe, &next.path);
+                true
+            })
+        };
+        let mut leg_result = solve_longest(&work, leg[0], leg[1], opts, budget, &keeps_later_legs);
+        if leg_result.path.is_empty() {
+            let shortest = solve_dijkstra(&work, leg[0], leg[1], opts);
+            if !shortest.path.is_empty() && keeps_later_legs(&shortest.path) {
+                leg_result = SearchResult {
+                    expanded: leg_result.expanded + shortest.expanded,
+                    exact: false,
+                    ..shortest
+                };
+            }
+        }
+        for &idx in &reserved {
+            work.walls[idx] = false;
+        }
+        result.expanded += leg_result.expanded;
+        result.exact &= leg_result.exact;
+        if leg_result.path.is_empty() {
+            result.path.clear();
+            result.waypoints.clear();
+            return result;
+        }
+        for &(x, y) in &leg_result.path {
+            work.walls[y * work.width + x] = true;
+        }
+        result.path.extend(&leg_result.path[1..]);
+        result.waypoints.push(result.path.len() - 1);
+    }
+
+    result
+}
+
+type PathFilter<'a> = dyn Fn(&[(usize, usize)]) -> bool + 'a;
+
+fn solve_longest(
+    grid: &Grid,
+    start: (usize, usize),
+    goal: (usize, usize),
+    opts: &SearchOptions,
+    budget: LongestPathBudget,
+    accept: &PathFilter<'_>,
+) -> SearchResult {
+    let seed_opts = SearchOptions {
+        maximize: true,
+        ..opts.clone()
+    };
+    let seed = solve_dijkstra(grid, start, goal, &seed_opts);
+    if seed.path.is_empty() {
+        return seed;
+    }
+
+    let moves = &opts.moves;
+    let path = extend_with_detours(grid, moves, seed.path);
+    let (path, cost) = if accept(&path) {
+        let cost = path_cost(grid, moves, &path);
+        (path, Some(cost))
+    } else {
+        (vec![], None)
+    };
+    let passable = grid.walls.iter().filter(|&&wall| !wall).count();
+
+    if passable > budget.max_cells {
+        return SearchResult {
+            waypoints: if path.is_empty() {
+                vec![]
+            } else {
+                vec![0, path.len() - 1]
+            },
+            path,
+            expanded: seed.expanded,
+            exact: false,
+            meetings: vec![],
+        };
+    }
+
+    let mut search = LongestPathSearch {
+        grid,
+        moves,
+        goal,
+        accept,
+        entry_cap: grid
+            .data
+            .iter()
+            .map(|val| moves.max_entry_cost(grid, val))
+            .collect(),
+        visited: vec![false; grid.data.len()],
+        path: vec![start],
+        best: path,
+        best_cost: cost,
+        deadline: Instant::now() + budget.time_limit,
+        expanded: seed.expanded,
+        timed_out: false,
+    };
+    search.visited[start.1 * grid.width + start.0] = true;
+    search.explore(start, 0);
+
+    SearchResult {
+        waypoints: if search.best.is_empty() {
+            vec![]
+        } else {
+            vec![0, search.best.len() - 1]
+        },
+        path: search.best,
+        expanded: search.expanded,
+        exact: !search.timed_out,
+        meetings: vec![],
+    }
+}
+
+struct LongestPathSearch<'a> {
+    grid: &'a Grid,
+    moves: &'a Neighborhood,
+    goal: (usize, usize),
+    accept: &'a PathFilter<'a>,
+    entry_cap: Vec<u64>,
+    visited: Vec<bool>,
+    path: Vec<(usize, usize)>,
+    best: Vec<(usize, usize)>,
+    best_cost: Option<u64>,
+    deadline: Instant,
+    expanded: usize,
+    timed_out: bool,
+}
+
+impl LongestPathSearch<'_> {
+    fn explore(&mut self, position: (usize, usize), cost: u64) {
+        if self.timed_out {
+            return;
+        }
+        self.expanded += 1;
+        if self.expanded.is_multiple_of(1024) && Instant::now() > self.deadline {
+            self.timed_out = true;
+            return;
+        }
+
+        if position == self.goal {
+            if self.best_cost.is_none_or(|best| cost > best) && (self.accept)(&self.path) {
+                self.best_cost = Some(cost);
+                self.best = self.path.clone();
+            }
+            return;
+        }
+
+        match self.upper_bound(position) {
+            Some(bound) if self.best_cost.is_none_or(|best| cost + bound > best) => {}
+            _ => return,
+        }
+
+        let mut next: Vec<(usize, usize)> = self
+            .moves
+            .neighbors(self.grid, position)
+            .filter(|&(x, y)| !self.visited[y * self.grid.width + x])
+            .collect();
+        next.sort_by_key(|&neighbor| {
+            std::cmp::Reverse(self.moves.step_cost(self.grid, position, neighbor, false))
+        });
+
+        for neighbor in next {
+            let idx = neighbor.1 * self.grid.width + neighbor.0;
+            let step = self.moves.step_cost(self.grid, position, neighbor, false);
+            self.visited[idx] = true;
+            self.path.push(neighbor);
+            self.explore(neighbor, cost + step);
+            self.path.pop();
+            self.visited[idx] = false;
+        }
+    }
+
+    fn upper_bound(&self, from: (usize, usize)) -> Option<u64> {
+        let mut seen = self.visited.clone();
+        let mut stack = vec![from];
+        let mut bound = 0;
+        let mut reaches_goal = false;
+
+        while let Some(position) = stack.pop() {
+            if position == self.goal {
+                reaches_goal = true;
+                continue;
+            }
+            for (x, y) in self.moves.neighbors(self.grid, position) {
+                let idx = y * self.grid.width + x;
+                if !seen[idx] {
+                    seen[idx] = true;
+                    bound += self.entry_cap[idx];
+                    stack.push((x, y));
+                }
+            }
+        }
+
+        reaches_goal.then_some(bound)
+    }
+}
+
+fn extend_with_detours(
+    grid: &Grid,
+    moves: &Neighborhood,
+    mut path: Vec<(usize, usize)>,
+) -> Vec<(usize, usize)> {
+    let idx = |(x, y): (usize, usize)| y * grid.width + x;
+    let cost = |from, to| moves.step_cost(grid, from, to, false);
+    let mut on_path = vec![false; grid.data.len()];
+    for &position in &path {
+        on_path[idx(position)] = true;
+    }
+
+    let mut i = 0;
+    while i + 1 < path.len() {
+        let (a, b) = (path[i], path[i + 1]);
+        let direct = cost(a, b);
+        let mut best: Option<(u64, Vec<(usize, usize)>)> = None;
+
+        for c in moves.neighbors(grid, a) {
+            if on_path[idx(c)] {
+                continue;
+            }
+            if moves.neighbors(grid, c).any(|n| n == b) {
+                let gain = (cost(a, c) + cost(c, b)).saturating_sub(direct);
+                if gain > best.as_ref().map_or(0, |(g, _)| *g) {
+                    best = Some((gain, vec![c]));
+                }
+            }
+            for d in moves.neighbors(grid, c) {
+                if on_path[idx(d)] || !moves.neighbors(grid, d).any(|n| n == b) {
+                    continue;
+                }
+                let gain = (cost(a, c) + cost(c, d) + cost(d, b)).saturating_sub(direct);
+                if gain > best.as_ref().map_or(0, |(g, _)| *g) {
+                    best = Some((gain, vec![c, d]));
+                }
+            }
+        }
+
+        match best {
+            Some((_, detour)) => {
+                for &position in &detour {
+                    on_path[idx(position)] = true;
+                }
+                path.splice(i + 1..i + 1, detour);
+            }
+            None => i += 1,
+        }
+    }
+
+    path
+}
+
+pub(crate) fn path_cost(grid: &Grid, moves: &Neighborhood, path: &[(usize, usize)]) -> u64 {
+    path.windows(2)
+        .map(|step| moves.step_cost(grid, step[0], step[1], false))
+        .sum()
+}
+
+pub(crate) fn reconstruct_path(
+    came_from: &[usize],
+    width: usize,
+    current: (usize, usize),
+) -> Vec<(usize, usize)> {
+    let mut path = vec![current];
+    let mut curr = current.1 * width + current.0;
+    while came_from[curr] != usize::MAX {
+        curr = came_from[curr];
+        path.push((curr % width, curr / width));
+    }
+    path.reverse();
+    path
+}
+
+pub fn generate_map(
+    w: usize,
+    h: usize,
+    params: GeneratorParams,
+    cell_width: CellWidth,
+    rng: &mut impl Rng,
+) -> Cells {
+    let max = cell_width.max_value() as f64;
+    let values = match params.generator {
+        Generator::Uniform => return generate_uniform(w, h, cell_width, rng),
+        Generator::Noise => {
+            let noise = value_noise(w, h, params.scale, params.octaves, rng);
+            noise.iter().map(|&n| (n * max).round() as u32).collect()
+        }
+        Generator::Valleys => generate_valleys(w, h, params, rng)
+            .into_iter()
+            .map(|byte| cell_width.widen(byte))
+            .collect(),
+        Generator::Maze => generate_maze(w, h, params.corridor, rng)
+            .into_iter()
+            .map(|byte| cell_width.widen(byte))
+            .collect(),
+        Generator::Gradient => generate_gradient(w, h, params, max, rng),
+    };
+    Cells::from_values(cell_width, values)
+}
+
+pub fn generate_uniform(w: usize, h: usize, cell_width: CellWidth, rng: &mut impl Rng) -> Cells {
+    let mut map = match cell_width {
+        CellWidth::U8 => Cells::U8((0..w * h).map(|_| rng.random()).collect()),
+        CellWidth::U16 => Cells::U16((0..w * h).map(|_| rng.random()).collect()),
+        CellWidth::U32 => Cells::U32((0..w * h).map(|_| rng.random()).collect()),
+    };
+
+    let last_idx = map.len() - 1;
+    map.set(0, 0);
+    map.set(last_idx, cell_width.max_value());
+    map
+}
+
+fn value_noise(w: usize, h: usize, scale: f64, octaves: u32, rng: &mut impl Rng) -> Vec<f64> {
+    let mut noise = vec![0.0; w * h];
+    let mut amplitude = 1.0;
+    let mut cell_size = scale;
+
+    for _ in 0..octaves {
+        let lw = (w as f64 / cell_size).ceil() as usize + 2;
+        let lh = (h as f64 / cell_size).ceil() as usize + 2;
+        let lattice: Vec<f64> = (0..lw * lh).map(|_| rng.random()).collect();
+        let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+
+        for y in 0..h {
+            for x in 0..w {
+                let fx = x as f64 / cell_size;
+                let fy = y as f64 / cell_size;
+                let (x0, y0) = (fx as usize, fy as usize);
+                let (tx, ty) = (smooth(fx.fract()), smooth(fy.fract()));
+                let at = |lx: usize, ly: usize| lattice[ly * lw + lx];
+                let top = at(x0, y0) + (at(x0 + 1, y0) - at(x0, y0)) * tx;
+                let bottom = at(x0, y0 + 1) + (at(x0 + 1, y0 + 1) - at(x0, y0 + 1)) * tx;
+                noise[y * w + x] += (top + (bottom - top) * ty) * amplitude;
+            }
+        }
+
+        amplitude *= 0.5;
+        cell_size = (cell_size / 2.0).max(1.0);
+    }
+
+    let min = noise.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = noise.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let range = if max > min { max - min } else { 1.0 };
+    noise.iter().map(|&n| (n - min) / range).collect()
+}
+
+fn generate_valleys(w: usize, h: usize, params: GeneratorParams, rng: &mut impl Rng) -> Vec<u8> {
+    let noise = value_noise(w, h, params.scale, params.octaves, rng);
+    let mut map: Vec<u8> = noise.iter().map(|&n| 0x80 + (n * 127.0) as u8).collect();
+
+    for _ in 0..params.count {
+        let (mut x, mut y) = if rng.random_bool(0.5) {
+            (0, rng.random_range(0..h))
+        } else {
+            (rng.random_range(0..w), 0)
+        };
+        for _ in 0..(w + h) * 2 {
+            map[y * w + x] = rng.random_range(0x00..=0x20);
+            match rng.random_range(0..4) {
+                0 if x > 0 => x -= 1,
+                1 if y > 0 => y -= 1,
+                2 if y + 1 < h => y += 1,
+                _ if x + 1 < w => x += 1,
+                _ if y + 1 < h => y += 1,
+                _ => break,
+            }
+        }
+    }
+    map
+}
+
+fn generate_maze(w: usize, h: usize, corridor: usize, rng: &mut impl Rng) -> Vec<u8> {
+    let pitch = corridor + 1;
+    let (mw, mh) = (w.div_ceil(pitch), h.div_ceil(pitch));
+    let mut open = vec![false; w * h];
+    let mut carve = |x0: usize, y0: usize, cw: usize, ch: usize| {
+        for y in y0..(y0 + ch).min(h) {
+            for x in x0..(x0 + cw).min(w) {
+                open[y * w + x] = true;
+            }
+        }
+    };
+
+    let mut visited = vec![false; mw * mh];
+    let mut stack: Vec<(usize, usize)> = vec![(0, 0)];
+    visited[0] = true;
+    carve(0, 0, corridor, corridor);
+
+    let mut order = [0usize, 1, 2, 3];
+    while let Some(&(cx, cy)) = stack.last() {
+        for i in (1..order.len()).rev() {
+            order.swap(i, rng.random_range(0..=i));
+        }
+        let next = order.iter().find_map(|&dir| {
+            let (nx, ny) = match dir {
+                0 => (cx.checked_sub(1)?, cy),
+                1 => (cx + 1, cy),
+                2 => (cx, cy.checked_sub(1)?),
+                _ => (cx, cy + 1),
+            };
+            (nx < mw && ny < mh && !visited[ny * mw + nx]).then_some((nx, ny))
+        });
+
+        match next {
+            Some((nx, ny)) => {
+                visited[ny * mw + nx] = true;
+                let (x0, y0) = (cx.min(nx) * pitch, cy.min(ny) * pitch);
+                if nx != cx {
+                    carve(x0, y0, pitch + corridor, corridor);
+                } else {
+                    carve(x0, y0, corridor, pitch + corridor);
+                }
+                stack.push((nx, ny));
+            }
+            None => {
+                stack.pop();
+            }
+        }
+    }
+
+    open.iter()
+        .map(|&corridor| {
+            if corridor {
+                rng.random_range(0x00..=0x20)
+            } else {
+                rng.random_range(0xE0..=0xFF)
+            }
+        })
+        .collect()
+}
+
+fn generate_gradient(
+    w: usize,
+    h: usize,
+    params: GeneratorParams,
+    cell_max: f64,
+    rng: &mut impl Rng,
+) -> Vec<u32> {
+    let (sin, cos) = params.angle.to_radians().sin_cos();
+    let projections: Vec<f64> = (0..w * h)
+        .map(|i| (i % w) as f64 * cos + (i / w) as f64 * sin)
+        .collect();
+    let min = projections.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = projections
+        .iter()
+        .cloned()
+        .fold(f64::NEG_INFINITY, f64::max);
+    let range = if max > min { max - min } else { 1.0 };
+
+    projections
+        .iter()
+        .map(|&p| {
+            let ramp = (p - min) / range;
+            let jitter: f64 = rng.random();
+            ((ramp * (1.0 - params.roughness) + jitter * params.roughness) * cell_max).round()
+                as u32
+        })
+        .collect()
+}
+
+pub fn sprinkle_obstacles(
+    grid: &mut Grid,
+    density: f64,
+    route: &[(usize, usize)],
+    rng: &mut impl Rng,
+) {
+    fn staircase(
+        protected: &mut [bool],
+        width: usize,
+        (mut x, mut y): (usize, usize),
+        (tx, ty): (usize, usize),
+        rng: &mut impl Rng,
+    ) {
+        protected[y * width + x] = true;
+        while (x, y) != (tx, ty) {
+            let move_x = y == ty || (x != tx && rng.random_bool(0.5));
+            if move_x {
+                x = if x < tx { x + 1 } else { x - 1 };
+            } else {
+                y = if y < ty { y + 1 } else { y - 1 };
+            }
+            protected[y * width + x] = true;
+        }
+    }
+
+    let mut protected = vec![false; grid.data.len()];
+    let (width, rows) = (grid.width, grid.layer_height());
+    for leg in route.windows(2) {
+        let (from, to) = (leg[0], leg[1]);
+        let (from_layer, to_layer) = (from.1 / rows, to.1 / rows);
+        let layers = from_layer.min(to_layer)..=from_layer.max(to_layer);
+        let stair = (from_layer != to_layer)
+            .then(|| {
+                (0..rows)
+                    .flat_map(|y| (0..width).map(move |x| (x, y)))
+                    .filter(|&(x, y)| layers.clone().all(|z| grid.is_stair(x, z * rows + y)))
+                    .min_by_key(|&(x, y)| x.abs_diff(from.0) + y.abs_diff(from.1 % rows))
+            })
+            .flatten();
+        let Some((sx, sy)) = stair else {
+            staircase(&mut protected, width, from, to, rng);
+            continue;
+        };
+        staircase(
+            &mut protected,
+            width,
+            from,
+            (sx, from_layer * rows + sy),
+            rng,
+        );
+        for z in layers {
+            protected[(z * rows + sy) * width + sx] = true;
+        }
+        staircase(&mut protected, width, (sx, to_layer * rows + sy), to, rng);
+    }
+
+    for (wall, &keep) in grid.walls.iter_mut().zip(&protected) {
+        if !keep && rng.random_bool(density) {
+            *wall = true;
+        }
+    }
+}
+
+pub fn save_map(grid: &Grid, path: &str, format: MapFormat) -> Result<(), MapError> {
+    let content = match format {
+        MapFormat::Text => encode_text_map(grid).into_bytes(),
+        MapFormat::Binary => encode_binary_map(grid, false),
+        MapFormat::BinaryRle => encode_binary_map(grid, true),
+    };
+    fs::write(path, content).map_err(|e| MapError::Io(e.to_string()))
+}
+
+pub fn encode_text_map(grid: &Grid) -> String {
+    let mut content = String::new();
+    if let Some(seed) = grid.seed {
+        content.push_str(&format!("# seed: {}\n", seed));
+    }
+    for y in 0..grid.height {
+        if y > 0 && y % grid.layer_height() == 0 {
+            content.push_str(LAYER_SEPARATOR);
+            content.push('\n');
+        }
+        for x in 0..grid.width {
+            if grid.is_wall(x, y) {
+                content.push_str(&"X".repeat(grid.cell_width().digits()));
+            } else {
+                content.push_str(&grid.hex(x, y));
+                if grid.is_stair(x, y) {
+                    content.push('^');
+                }
+            }
+            content.push(' ');
+        }
+        content.push('\n');
+    }
+    content
+}
+
+pub fn encode_binary_map(grid: &Grid, rle: bool) -> Vec<u8> {
+    let has_walls = grid.walls.iter().any(|&wall| wall);
+    let mut flags = 0;
+    if rle {
+        flags |= FLAG_RLE;
+    }
+    if has_walls {
+        flags |= FLAG_WALLS;
+    }
+    if grid.seed.is_some() {
+        flags |= FLAG_SEED;
+    }
+    if grid.layers > 1 {
+        flags |= FLAG_LAYERS;
+    }
+
+    let cell_bytes = grid.cell_width().bytes();
+    let push_cell = |bytes: &mut Vec<u8>, val: u32| {
+        bytes.extend_from_slice(&val.to_le_bytes()[..cell_bytes]);
+    };
+    let mut bytes = Vec::with_capacity(BINARY_HEADER_LEN + grid.data.len() * cell_bytes);
+    bytes.extend_from_slice(BINARY_MAGIC);
+    bytes.extend_from_slice(&[BINARY_VERSION, flags, cell_bytes as u8, 0]);
+    bytes.extend_from_slice(&(grid.width as u32).to_le_bytes());
+    bytes.extend_from_slice(&(grid.height as u32).to_le_bytes());
+    if let Some(seed) = grid.seed {
+        bytes.extend_from_slice(&seed.to_le_bytes());
+    }
+    if grid.layers > 1 {
+        bytes.extend_from_slice(&(grid.layers as u32).to_le_bytes());
+    }
+
+    if rle {
+        let mut values = grid.data.iter().peekable();
+        while let Some(val) = values.next() {
+            let mut count = 1;
+            while count < 255 && values.next_if_eq(&val).is_some() {
+                count += 1;
+            }
+            bytes.push(count);
+            push_cell(&mut bytes, val);
+        }
+    } else {
+        for val in grid.data.iter() {
+            push_cell(&mut bytes, val);
+        }
+    }
+
+    let pack = |bits: &[bool]| {
+        let mut packed = vec![0u8; bits.len().div_ceil(8)];
+        for (i, _) in bits.iter().enumerate().filter(|&(_, &bit)| bit) {
+            packed[i / 8] |= 1 << (i % 8);
+        }
+        packed
+    };
+    if has_walls {
+        bytes.extend(pack(&grid.walls));
+    }
+    if grid.layers > 1 {
+        bytes.extend(pack(&grid.stairs));
+    }
+    bytes
+}
+
+pub fn decode_binary_map(bytes: &[u8]) -> Result<Grid, String> {
+    if bytes.len() < BINARY_HEADER_LEN {
+        return Err("en-tête tronqué".to_string());
+    }
+    let version = bytes[4];
+    let flags = bytes[5];
+    let cell_width = bytes[6];
+    if version == 0 || version > BINARY_VERSION {
+        return Err(format!("version {} non supportée", version));
+    }
+    let cell_width = CellWidth::from_bytes(cell_width as usize)
+        .ok_or_else(|| format!("largeur de cellule {} non supportée", cell_width))?;
+    let cell_bytes = cell_width.bytes();
+    let read_cell = |chunk: &[u8]| {
+        let mut le = [0u8; 4];
+        le[..cell_bytes].copy_from_slice(chunk);
+        u32::from_le_bytes(le)
+    };
+    let width = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+    let height = u32::from_le_bytes(bytes[12..16].try_into().unwrap()) as usize;
+    let cells = width
+        .checked_mul(height)
+        .filter(|&cells| cells <= BINARY_MAX_CELLS)
+        .ok_or_else(|| format!("dimensions trop grandes: {}x{}", width, height))?;
+    if cells == 0 {
+        return Err(format!("dimensions nulles: {}x{}", width, height));
+    }
+
+    let mut rest = &bytes[BINARY_HEADER_LEN..];
+    let seed = if flags & FLAG_SEED != 0 {
+        let (seed, tail) = rest
+            .split_first_chunk::<8>()
+            .ok_or_else(|| "graine tronquée".to_string())?;
+        rest = tail;
+        Some(u64::from_le_bytes(*seed))
+    } else {
+        None
+    };
+    let layers = if flags & FLAG_LAYERS != 0 {
+        let (layers, tail) = rest
+            .split_first_chunk::<4>()
+            .ok_or_else(|| "nombre de couches tronqué".to_string())?;
+        rest = tail;
+        u32::from_le_bytes(*layers) as usize
+    } else {
+        1
+    };
+    if layers == 0 || !height.is_multiple_of(layers) {
+        return Err(format!(
+            "{} couche(s) incompatible(s) avec une hauteur de {}",
+            layers, height
+        ));
+    }
+    let data = if flags & FLAG_RLE != 0 {
+        if cells > rest.len() / (1 + cell_bytes) * 255 {
+            return Err("données RLE tronquées".to_string());
+        }
+        let mut data = Cells::new(cell_width);
+        while data.len() < cells {
+            let Some((&count, tail)) = rest
+                .split_first()
+                .filter(|(_, tail)| tail.len() >= cell_bytes)
+            else {
+                return Err("données RLE tronquées".to_string());
+            };
+            if count == 0 || data.len() + count as usize > cells {
+                return Err("longueur de séquence RLE invalide".to_string());
+            }
+            let (val, tail) = tail.split_at(cell_bytes);
+            data.extend(std::iter::repeat_n(read_cell(val), count as usize));
+            rest = tail;
+        }
+        data
+    } else {
+        let size = cells
+            .checked_mul(cell_bytes)
+            .filter(|&size| size <= rest.len())
+            .ok_or_else(|| "données tronquées".to_string())?;
+        let (data, tail) = rest.split_at(size);
+        rest = tail;
+        Cells::from_values(cell_width, data.chunks(cell_bytes).map(read_cell))
+    };
+
+    let mut grid = Grid::new(width, height, data);
+    grid.seed = seed;
+    grid.layers = layers;
+    let mut unpack = |bits: &mut [bool], what: &str| {
+        if rest.len() < cells.div_ceil(8) {
+            return Err(format!("masque de {} tronqué", what));
+        }
+        for (i, bit) in bits.iter_mut().enumerate() {
+            *bit = rest[i / 8] & (1 << (i % 8)) != 0;
+        }
+        rest = &rest[cells.div_ceil(8)..];
+        Ok(())
+    };
+    if flags & FLAG_WALLS != 0 {
+        unpack(&mut grid.walls, "murs")?;
+    }
+    if layers > 1 {
+        unpack(&mut grid.stairs, "escaliers")?;
+    }
+    Ok(grid)
+}
+
+pub fn load_map(path: &str) -> Result<Grid, Vec<MapError>> {
+    let bytes = fs::read(path).map_err(|e| vec![MapError::Io(e.to_string())])?;
+    if bytes.starts_with(BINARY_MAGIC) {
+        return decode_binary_map(&bytes).map_err(|e| vec![MapError::Binary(e)]);
+    }
+
+    let content = String::from_utf8(bytes).map_err(|_| vec![MapError::Encoding])?;
+    parse_text_map(&content)
+}
+
+pub fn parse_text_map(content: &str) -> Result<Grid, Vec<MapError>> {
+    let mut map = None;
+    let mut walls = Vec::new();
+    let mut stairs = Vec::new();
+    let mut errors = Vec::new();
+    let mut width = 0;
+    let mut height = 0;
+    let mut seed = None;
+    let mut digits = None;
+    let mut layer_ends = Vec::new();
+
+    for (line_idx, line) in content.lines().enumerate() {
+        if line.trim() == LAYER_SEPARATOR {
+            layer_ends.push((line_idx + 1, height));
+            continue;
+        }
+        if let Some(comment) = line.trim_start().strip_prefix('#') {
+            if let Some(value) = comment.trim().strip_prefix("seed:") {
+                match value.trim().parse() {
+                    Ok(val) => seed = Some(val),
+                    Err(_) => errors.push(MapError::InvalidToken {
+                        line: line_idx + 1,
+                        column: line.find("seed:").unwrap() + 6,
+                        token: value.trim().to_string(),
+                    }),
+                }
+            }
+            continue;
+        }
+
+        let mut count = 0;
+        for (column, token) in split_tokens(line) {
+            count += 1;
+            let (token, is_stair) = match token.strip_suffix('^') {
+                Some(value) => (value, true),
+                None => (token, false),
+            };
+            let is_wall = token.bytes().all(|b| b.eq_ignore_ascii_case(&b'X'));
+            let is_value = token.bytes().all(|b| b.is_ascii_hexdigit());
+            if !matches!(token.len(), 2 | 4 | 8) || !(is_wall || is_value) || (is_wall && is_stair)
+            {
+                errors.push(MapError::InvalidToken {
+                    line: line_idx + 1,
+                    column,
+                    token: format!("{}{}", token, if is_stair { "^" } else { "" }),
+                });
+            } else if *digits.get_or_insert(token.len()) != token.len() {
+                errors.push(MapError::MixedWidth {
+                    line: line_idx + 1,
+                    column,
+                    expected: digits.unwrap(),
+                    found: token.len(),
+                });
+            } else {
+                let value = if is_wall {
+                    0
+                } else {
+                    u32::from_str_radix(token, 16).unwrap()
+                };
+                map.get_or_insert_with(|| {
+                    Cells::new(CellWidth::from_bytes(token.len() / 2).unwrap())
+                })
+                .push(value);
+                walls.push(is_wall);
+                stairs.push(is_stair);
+            }
+        }
+
+        if count == 0 {
+            continue;
+        }
+        if width == 0 {
+            width = count;
+        } else if count != width {
+            errors.push(MapError::RaggedRow {
+                line: line_idx + 1,
+                expected: width,
+                found: count,
+            });
+        }
+        height += 1;
+    }
+
+    let mut layer_rows = height;
+    if !layer_ends.is_empty() {
+        layer_ends.push((content.lines().count(), height));
+        layer_rows = layer_ends[0].1;
+        let mut start = 0;
+        for &(line, end) in &layer_ends {
+            if end - start != layer_rows {
+                errors.push(MapError::UnevenLayer {
+                    line,
+                    expected: layer_rows,
+                    found: end - start,
+                });
+            }
+            start = end;
+        }
+    }
+    if width == 0 {
+        errors.push(MapError::Empty);
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(Grid {
+        width,
+        height,
+        data: map.unwrap_or_else(|| Cells::new(CellWidth::U8)),
+        walls,
+        seed,
+        layers: height / layer_rows,
+        stairs,
+    })
+}
+
+fn split_tokens(line: &str) -> Vec<(usize, &str)> {
+    let mut tokens = Vec::new();
+    let mut current: Option<(usize, usize)> = None;
+
+    for (column, (i, c)) in line.char_indices().enumerate() {
+        match (c.is_whitespace(), current) {
+            (false, None) => current = Some((column + 1, i)),
+            (true, Some((col, start))) => {
+                tokens.push((col, &line[start..i]));
+                current = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some((col, start)) = current {
+        tokens.push((col, &line[start..]));
+    }
+    tokens
+}
+
+pub fn apply_mask(grid: &mut Grid, path: &str) -> Result<(), String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let rows: Vec<Vec<&str>> = content
+        .lines()
+        .filter(|line| line.trim() != LAYER_SEPARATOR)
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .filter(|row| !row.is_empty())
+        .collect();
+
+    if rows.len() != grid.height || rows.iter().any(|row| row.len() != grid.width) {
+        return Err(format!(
+            "dimensions différentes de la grille {}x{}",
+            grid.width, grid.height
+        ));
+    }
+
+    for (y, row) in rows.iter().enumerate() {
+        for (x, token) in row.iter().enumerate() {
+            let wall = match *token {
+                "#" | "X" | "XX" | "x" | "xx" | "1" => true,
+                "." | "0" | "00" => false,
+                other => return Err(format!("jeton '{}' invalide en ({},{})", other, x, y)),
+            };
+            grid.walls[y * grid.width + x] |= wall;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn options(topology: Topology, heuristic: Heuristic, queue: QueueKind) -> SearchOptions {
+        SearchOptions {
+            moves: Neighborhood {
+                topology,
+                diagonal_cost: std::f64::consts::SQRT_2,
+                cost: CostModel::Entered,
+                wrap: false,
+            },
+            heuristic,
+            maximize: false,
+            queue,
+            bidirectional: false,
+            max_steps: None,
+        }
+    }
+
+    fn random_grid(seed: u64, w: usize, h: usize, obstacles: f64) -> Grid {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let data = generate_uniform(w, h, CellWidth::U8, &mut rng);
+        let mut grid = Grid::new(w, h, data);
+        sprinkle_obstacles(&mut grid, obstacles, &[(0, 0), (w - 1, h - 1)], &mut rng);
+        grid
+    }
+
+    fn cost(grid: &Grid, opts: &SearchOptions, result: &SearchResult) -> Option<u64> {
+        (!result.path.is_empty()).then(|| path_cost(grid, &opts.moves, &result.path))
+    }
+
+    #[test]
+    fn bidirectional_cost_matches_unidirectional() {
+        let cases = [
+            (Topology::Ortho, Heuristic::Zero),
+            (Topology::Ortho, Heuristic::Manhattan),
+            (Topology::Diag, Heuristic::Chebyshev),
+            (Topology::HexOddR, Heuristic::Hex),
+            (Topology::HexEvenQ, Heuristic::Zero),
+        ];
+        for seed in 0..40 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let (w, h) = (rng.random_range(1..24), rng.random_range(1..24));
+            let grid = random_grid(seed, w, h, rng.random_range(0.0..0.4));
+            let start = (rng.random_range(0..w), rng.random_range(0..h));
+            let goal = (rng.random_range(0..w), rng.random_range(0..h));
+            if grid.is_wall(start.0, start.1) || grid.is_wall(goal.0, goal.1) {
+                continue;
+            }
+
+            for (topology, heuristic) in cases {
+                for queue in [QueueKind::BinaryHeap, QueueKind::Bucket] {
+                    let opts = options(topology, heuristic, queue);
+                    let uni = solve_dijkstra(&grid, start, goal, &opts);
+                    let bi = solve_bidirectional(&grid, start, goal, &opts);
+                    assert_eq!(
+                        cost(&grid, &opts, &bi),
+                        cost(&grid, &opts, &uni),
+                        "seed {} {:?} {:?} {:?}",
+                        seed,
+                        topology,
+                        heuristic,
+                        queue
+                    );
+                    if let Some(&meeting) = bi.meetings.first() {
+                        assert!(bi.path.contains(&meeting));
+                        assert_eq!(bi.path.first(), Some(&start));
+                        assert_eq!(bi.path.last(), Some(&goal));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bidirectional_handles_maximize_and_unreachable_goal() {
+        let mut opts = options(Topology::Ortho, Heuristic::Zero, QueueKind::BinaryHeap);
+        opts.maximize = true;
+        let grid = random_grid(7, 16, 16, 0.0);
+        let uni = solve_dijkstra(&grid, (0, 0), (15, 15), &opts);
+        let bi = solve_bidirectional(&grid, (0, 0), (15, 15), &opts);
+        assert_eq!(
+            path_cost(&grid, &opts.moves, &bi.path),
+            path_cost(&grid, &opts.moves, &uni.path)
+        );
+
+        let mut walled = Grid::new(3, 3, Cells::U8(vec![1; 9]));
+        for y in 0..3 {
+            walled.walls[y * 3 + 1] = true;
+        }
+        let result = solve_bidirectional(&walled, (0, 0), (2, 2), &opts);
+        assert!(result.path.is_empty());
+        assert!(result.meetings.is_empty());
+    }
+
+    #[test]
+    fn wide_text_maps_round_trip() {
+        let grid = parse_text_map("0000 FFFF\nXXXX 1234\n").unwrap();
+        assert_eq!(grid.cell_width(), CellWidth::U16);
+        assert_eq!(grid.data, Cells::U16(vec![0, 0xFFFF, 0, 0x1234]));
+        assert!(grid.is_wall(0, 1));
+        assert_eq!(encode_text_map(&grid), "0000 FFFF \nXXXX 1234 \n");
+
+        let Err(errors) = parse_text_map("00 0000\n") else {
+            panic!("mixed cell widths accepted");
+        };
+        assert!(matches!(
+            errors[..],
+            [MapError::MixedWidth {
+                expected: 2,
+                found: 4,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn wide_binary_maps_round_trip() {
+        for cell_width in [CellWidth::U8, CellWidth::U16, CellWidth::U32] {
+            let mut rng = StdRng::seed_from_u64(3);
+            let data = generate_uniform(9, 7, cell_width, &mut rng);
+            let mut grid = Grid::new(9, 7, data);
+            grid.walls[5] = true;
+            for rle in [false, true] {
+                let decoded = decode_binary_map(&encode_binary_map(&grid, rle)).unwrap();
+                assert_eq!(decoded.cell_width(), cell_width);
+                assert_eq!(decoded.data, grid.data);
+                assert_eq!(decoded.walls, grid.walls);
+            }
+        }
+    }
+
+    #[test]
+    fn binary_headers_are_checked_before_decoding() {
+        let header = |flags: u8, width: u32, height: u32| {
+            let mut bytes = BINARY_MAGIC.to_vec();
+            bytes.extend_from_slice(&[BINARY_VERSION, flags, 1, 0]);
+            bytes.extend_from_slice(&width.to_le_bytes());
+            bytes.extend_from_slice(&height.to_le_bytes());
+            bytes
+        };
+        assert!(decode_binary_map(&header(FLAG_RLE, 0xFFFF, 0xFFFF)).is_err());
+        assert!(decode_binary_map(&header(0, 0xFFFF_FFFF, 0xFFFF_FFFF)).is_err());
+        let mut truncated = header(FLAG_RLE, 600, 1);
+        truncated.extend_from_slice(&[255, 7, 255, 7]);
+        assert_eq!(
+            decode_binary_map(&truncated).err().as_deref(),
+            Some("données RLE tronquées")
+        );
+        assert!(decode_binary_map(&header(0, 3, 3)[..10]).is_err());
+        assert_eq!(
+            decode_binary_map(&header(0, 0, 0)).err().as_deref(),
+            Some("dimensions nulles: 0x0")
+        );
+    }
+
+    #[test]
+    fn layered_maps_round_trip_and_connect_through_stairs() {
+        let text = "05 01 \n01^ 05 \n---\n05 05 \n01^ 01 \n";
+        let grid = parse_text_map(text).unwrap();
+        assert_eq!((grid.layers, grid.layer_height()), (2, 2));
+        assert!(grid.is_stair(0, 1) && grid.is_stair(0, 3));
+        assert_eq!(grid.label((1, 3)), "(1,1,1)");
+        assert_eq!(encode_text_map(&grid), text);
+        for rle in [false, true] {
+            let decoded = decode_binary_map(&encode_binary_map(&grid, rle)).unwrap();
+            assert_eq!(decoded.layers, 2);
+            assert_eq!(decoded.stairs, grid.stairs);
+            assert_eq!(decoded.data, grid.data);
+        }
+
+        let solver = Solver::new(
+            &grid,
+            SearchOptions::default(),
+            LongestPathBudget::default(),
+        )
+        .unwrap();
+        let path = solver.shortest(&[(1, 0), grid.position(1, 1, 1)]).unwrap();
+        assert_eq!(path.cost, 8);
+        assert!(path.cells.windows(2).any(|w| w == [(0, 1), (0, 3)]));
+
+        let Err(errors) = parse_text_map("01\n---\n01\n01\n") else {
+            panic!("uneven layers accepted");
+        };
+        assert!(matches!(
+            errors[..],
+            [MapError::UnevenLayer {
+                line: 4,
+                expected: 1,
+                found: 2,
+            }]
+        ));
+    }
+
+    #[test]
+    fn obstacles_keep_layered_routes_connected() {
+        for seed in 0..40 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let data = generate_uniform(8, 24, CellWidth::U8, &mut rng);
+            let mut grid = Grid::new(8, 24, data);
+            grid.layers = 3;
+            for _ in 0..2 {
+                let (x, y) = (rng.random_range(0..8), rng.random_range(0..8));
+                for layer in 0..3 {
+                    let (x, y) = grid.position(x, y, layer);
+                    grid.stairs[y * 8 + x] = true;
+                }
+            }
+            let route = [(0, 0), grid.position(7, 7, 2), grid.position(3, 0, 1)];
+            sprinkle_obstacles(&mut grid, 0.45, &route, &mut rng);
+            let solver = Solver::new(
+                &grid,
+                SearchOptions::default(),
+                LongestPathBudget::default(),
+            )
+            .unwrap();
+            assert!(solver.shortest(&route).is_ok(), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn wraparound_searches_cross_edges_and_keep_heuristics_admissible() {
+        let grid = Grid::new(5, 5, Cells::U8(vec![1; 25]));
+        let mut opts = options(Topology::Ortho, Heuristic::Manhattan, QueueKind::BinaryHeap);
+        assert_eq!(
+            cost(&grid, &opts, &solve_dijkstra(&grid, (0, 0), (4, 4), &opts)),
+            Some(8)
+        );
+        opts.moves.wrap = true;
+        let neighbors: Vec<_> = opts.moves.neighbors(&grid, (0, 0)).collect();
+        assert!(neighbors.contains(&(4, 0)) && neighbors.contains(&(0, 4)));
+        assert_eq!(
+            cost(&grid, &opts, &solve_dijkstra(&grid, (0, 0), (4, 4), &opts)),
+            Some(2)
+        );
+        opts.moves.topology = Topology::HexOddR;
+        assert!(matches!(
+            Solver::new(&grid, opts, LongestPathBudget::default()),
+            Err(SolveError::Unsupported(_))
+        ));
+
+        let cases = [
+            (Topology::Ortho, Heuristic::Manhattan),
+            (Topology::Diag, Heuristic::Chebyshev),
+            (Topology::HexOddR, Heuristic::Hex),
+            (Topology::HexEvenQ, Heuristic::Hex),
+        ];
+        for seed in 0..30 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let (w, h) = (2 * rng.random_range(1..10), 2 * rng.random_range(1..10));
+            let grid = random_grid(seed, w, h, 0.2);
+            let goal = (w - 1, h - 1);
+            for (topology, heuristic) in cases {
+                let mut exact = options(topology, Heuristic::Zero, QueueKind::BinaryHeap);
+                exact.moves.wrap = true;
+                let guided = SearchOptions {
+                    heuristic,
+                    ..exact.clone()
+                };
+                assert_eq!(
+                    cost(
+                        &grid,
+                        &guided,
+                        &solve_dijkstra(&grid, (0, 0), goal, &guided)
+                    ),
+                    cost(&grid, &exact, &solve_dijkstra(&grid, (0, 0), goal, &exact)),
+                    "seed {} {:?}",
+                    seed,
+                    topology
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn long_paths_on_wide_cells_do_not_overflow() {
+        let grid = Grid::new(4, 1, Cells::U32(vec![0, u32::MAX, u32::MAX, u32::MAX]));
+        let opts = options(Topology::Ortho, Heuristic::Manhattan, QueueKind::BinaryHeap);
+        let result = solve_dijkstra(&grid, (0, 0), (3, 0), &opts);
+        assert_eq!(
+            path_cost(&grid, &opts.moves, &result.path),
+            3 * u32::MAX as u64
+        );
+    }
+
+    fn ortho() -> SearchOptions {
+        options(Topology::Ortho, Heuristic::Zero, QueueKind::BinaryHeap)
+    }
+
+    fn brute_force_longest(
+        grid: &Grid,
+        moves: &Neighborhood,
+        position: (usize, usize),
+        goal: (usize, usize),
+        path: &mut Vec<(usize, usize)>,
+    ) -> Option<u64> {
+        if position == goal {
+            return Some(path_cost(grid, moves, path));
+        }
+        let mut best = None;
+        for next in moves.neighbors(grid, position) {
+            if path.contains(&next) {
+                continue;
+            }
+            path.push(next);
+            best = best.max(brute_force_longest(grid, moves, next, goal, path));
+            path.pop();
+        }
+        best
+    }
+
+    #[test]
+    fn dijkstra_finds_known_cheapest_path() {
+        let grid = parse_text_map("01 09 01\n01 09 01\n01 01 01\n").unwrap();
+        let result = solve_dijkstra(&grid, (0, 0), (2, 0), &ortho());
+        assert_eq!(path_cost(&grid, &ortho().moves, &result.path), 6);
+        assert_eq!(result.path.len(), 7);
+        assert_eq!(
+            reconstruct_path(&[usize::MAX, 0, 1], 3, (2, 0)),
+            vec![(0, 0), (1, 0), (2, 0)]
+        );
+    }
+
+    #[test]
+    fn heuristics_and_queues_agree_on_cost() {
+        for seed in 0..10 {
+            let grid = random_grid(seed, 20, 15, 0.2);
+            let baseline = solve_dijkstra(&grid, (0, 0), (19, 14), &ortho());
+            for heuristic in [Heuristic::Zero, Heuristic::Manhattan] {
+                for queue in [QueueKind::BinaryHeap, QueueKind::Bucket] {
+                    let opts = options(Topology::Ortho, heuristic, queue);
+                    let result = solve_dijkstra(&grid, (0, 0), (19, 14), &opts);
+                    assert_eq!(cost(&grid, &opts, &result), cost(&grid, &opts, &baseline));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn route_through_waypoints_visits_them_in_order() {
+        let grid = random_grid(4, 10, 10, 0.0);
+        let route = [(0, 0), (9, 0), (0, 9), (9, 9)];
+        let result = solve_route(&grid, &route, &ortho(), None);
+        for (&index, &waypoint) in result.waypoints.iter().zip(&route) {
+            assert_eq!(result.path[index], waypoint);
+        }
+    }
+
+    #[test]
+    fn alternatives_are_sorted_and_distinct() {
+        let grid = random_grid(5, 8, 8, 0.1);
+        let (found, _) = k_shortest_paths(&grid, (0, 0), (7, 7), &ortho(), 4, 1);
+        assert!(!found.is_empty());
+        let best = solve_dijkstra(&grid, (0, 0), (7, 7), &ortho());
+        assert_eq!(found[0].cost, path_cost(&grid, &ortho().moves, &best.path));
+        for pair in found.windows(2) {
+            assert!(pair[0].cost <= pair[1].cost);
+            assert_ne!(pair[0].path, pair[1].path);
+        }
+    }
+
+    #[test]
+    fn longest_route_keeps_later_waypoints_reachable() {
+        let grid = Grid::new(4, 4, Cells::U8(vec![1; 16]));
+        let opts = options(Topology::Ortho, Heuristic::Zero, QueueKind::BinaryHeap);
+        let route = [(0, 0), (3, 0), (3, 3)];
+        let result = solve_longest_route(&grid, &route, &opts, LongestPathBudget::default());
+        assert_eq!(result.path.first(), Some(&(0, 0)));
+        assert_eq!(result.path.last(), Some(&(3, 3)));
+        assert_eq!(result.path[result.waypoints[1]], (3, 0));
+        let unique: HashSet<_> = result.path.iter().collect();
+        assert_eq!(unique.len(), result.path.len());
+
+        let grid = parse_text_map("44 96 B7 06\nCB 9C AA 3E\n").unwrap();
+        let solver = Solver::new(&grid, opts, LongestPathBudget::default()).unwrap();
+        let found = solver.longest(&[(3, 1), (0, 1), (2, 1)]).unwrap();
+        assert_eq!(found.cells.len(), 8);
+        assert_eq!(found.cells[5], (0, 1));
+    }
+
+    #[test]
+    fn longest_path_matches_brute_force() {
+        let models = [
+            CostModel::Entered,
+            CostModel::Slope,
+            CostModel::Squared,
+            CostModel::Step(7),
+        ];
+        for model in models {
+            let mut opts = ortho();
+            opts.moves.cost = model;
+            for seed in 0..30 {
+                let grid = random_grid(seed, 4, 3, 0.15);
+                let budget = LongestPathBudget::default();
+                let result = solve_longest(&grid, (0, 0), (3, 2), &opts, budget, &|_| true);
+                let expected =
+                    brute_force_longest(&grid, &opts.moves, (0, 0), (3, 2), &mut vec![(0, 0)]);
+                assert!(result.exact);
+                let found = cost(&grid, &opts, &result);
+                assert_eq!(found, expected, "{:?} seed {}", opts.moves.cost, seed);
+            }
+        }
+    }
+
+    #[test]
+    fn cost_field_agrees_with_point_searches() {
+        let grid = random_grid(6, 9, 9, 0.2);
+        let field = solve_cost_field(&grid, (0, 0), &ortho());
+        for y in 0..9 {
+            for x in 0..9 {
+                let result = solve_dijkstra(&grid, (0, 0), (x, y), &ortho());
+                assert_eq!(field.dist[y * 9 + x], cost(&grid, &ortho(), &result));
+                assert_eq!(field.path_to((x, y)).is_empty(), result.path.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn cost_field_export_loads_as_a_map() {
+        let mut grid = random_grid(11, 6, 6, 0.0);
+        grid.walls[7] = true;
+        let opts = options(Topology::Ortho, Heuristic::Zero, QueueKind::BinaryHeap);
+        let field = solve_cost_field(&grid, (0, 0), &opts);
+        let loaded = parse_text_map(&encode_cost_field(&field, false)).unwrap();
+        assert_eq!(loaded.cell_width(), CellWidth::U16);
+        for (idx, cost) in field.dist.iter().enumerate() {
+            assert_eq!(loaded.walls[idx], cost.is_none());
+            assert_eq!(loaded.data.get(idx) as u64, cost.unwrap_or(0));
+        }
+    }
+
+    #[test]
+    fn flow_field_leads_every_cell_to_its_cheapest_goal() {
+        for topology in [Topology::Ortho, Topology::Diag, Topology::HexOddR] {
+            let opts = options(topology, Heuristic::Zero, QueueKind::BinaryHeap);
+            let grid = random_grid(11, 10, 8, 0.2);
+            let goals = [(0, 0), (9, 7)];
+            let field = solve_flow_field(&grid, &goals, &opts);
+            for y in 0..8 {
+                for x in (0..10).filter(|&x| !grid.is_wall(x, y)) {
+                    let best = goals
+                        .iter()
+                        .filter_map(|&goal| {
+                            cost(&grid, &opts, &solve_dijkstra(&grid, (x, y), goal, &opts))
+                        })
+                        .min();
+                    assert_eq!(field.dist[y * 10 + x], best, "{:?} ({},{})", topology, x, y);
+                    if best.is_some() {
+                        let path = field.path_from((x, y));
+                        assert_eq!(path.first(), Some(&(x, y)));
+                        assert!(goals.contains(path.last().unwrap()));
+                        assert_eq!(Some(path_cost(&grid, &opts.moves, &path)), best);
+                    }
+                }
+            }
+        }
+
+        let grid = random_grid(3, 4, 4, 0.0);
+        let field = solve_cost_field(&grid, (0, 0), &ortho());
+        assert_eq!(flow_arrow(&field, Topology::Ortho, (1, 1), (1, 0)), '↑');
+        assert_eq!(flow_arrow(&field, Topology::HexOddR, (1, 1), (1, 0)), '↖');
+        assert_eq!(flow_arrow(&field, Topology::HexEvenQ, (1, 0), (2, 0)), '↘');
+    }
+
+    #[test]
+    fn flow_arrows_follow_wrap_seams_and_stairs() {
+        let mut grid = Grid::new(4, 8, Cells::U8(vec![1; 32]));
+        grid.layers = 2;
+        grid.stairs[0] = true;
+        grid.stairs[16] = true;
+        let mut opts = ortho();
+        opts.moves.wrap = true;
+        let field = solve_flow_field(&grid, &[(3, 0)], &opts);
+        assert_eq!(field.prev[0], Some((3, 0)));
+        assert_eq!(field.prev[16], Some((0, 0)));
+        assert_eq!(flow_arrow(&field, Topology::Ortho, (0, 0), (3, 0)), '←');
+        assert_eq!(flow_arrow(&field, Topology::Ortho, (0, 0), (0, 3)), '↑');
+        assert_eq!(flow_arrow(&field, Topology::Ortho, (0, 4), (0, 0)), '⇑');
+        assert_eq!(flow_arrow(&field, Topology::Ortho, (0, 0), (0, 4)), '⇓');
+
+        let text = encode_flow_field(&field, Topology::Ortho, false);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 9);
+        assert_eq!(lines[4], "---");
+        assert!(lines[5].starts_with("⇑ ← ") && lines[5].ends_with(" → "));
+    }
+
+    #[test]
+    fn multi_source_labels_cells_by_nearest_source() {
+        let grid = random_grid(12, 12, 9, 0.15);
+        let sources = [(0, 0), (11, 0), (6, 8)];
+        let field = solve_multi_source(&grid, &sources, &ortho());
+        let singles: Vec<CostField> = sources
+            .iter()
+            .map(|&source| solve_cost_field(&grid, source, &ortho()))
+            .collect();
+        for idx in 0..grid.data.len() {
+            let nearest = singles.iter().filter_map(|single| single.dist[idx]).min();
+            assert_eq!(field.dist[idx], nearest);
+            if let Some(owner) = field.owner[idx] {
+                assert_eq!(singles[owner].dist[idx], nearest);
+            }
+        }
+        assert_eq!(
+            field.region_sizes().iter().sum::<usize>(),
+            field.dist.iter().flatten().count()
+        );
+    }
+
+    #[test]
+    fn incremental_planner_matches_full_search_after_updates() {
+        let cases = [
+            (Topology::Ortho, Heuristic::Zero, CostModel::Entered),
+            (Topology::Ortho, Heuristic::Manhattan, CostModel::Entered),
+            (Topology::Diag, Heuristic::Chebyshev, CostModel::Slope),
+            (Topology::HexOddR, Heuristic::Hex, CostModel::Step(3)),
+        ];
+        for seed in 0..8 {
+            for (topology, heuristic, cost_model) in cases.clone() {
+                let mut opts = options(topology, heuristic, QueueKind::BinaryHeap);
+                opts.moves.cost = cost_model;
+                let mut rng = StdRng::seed_from_u64(seed);
+                let grid = random_grid(seed, 14, 10, 0.15);
+                let mut planner =
+                    IncrementalPlanner::new(grid, (0, 0), (13, 9), opts.clone()).unwrap();
+                for batch in 0..6 {
+                    let result = planner.plan();
+                    let full = solve_dijkstra(planner.grid(), (0, 0), (13, 9), &opts);
+                    assert_eq!(
+                        result.as_ref().ok().map(|result| result.cost),
+                        cost(planner.grid(), &opts, &full),
+                        "seed {} batch {} {:?}",
+                        seed,
+                        batch,
+                        topology
+                    );
+                    if let Ok(result) = result {
+                        assert_eq!(result.cells.first(), Some(&(0, 0)));
+                        assert_eq!(result.cells.last(), Some(&(13, 9)));
+                    }
+
+                    let mut updates = vec![];
+                    for _ in 0..rng.random_range(1..8) {
+                        let position = (rng.random_range(0..14), rng.random_range(0..10));
+                        if position != (0, 0) && position != (13, 9) {
+                            let value = rng.random_bool(0.8).then(|| rng.random_range(0..=255));
+                            updates.push(CellUpdate { position, value });
+                        }
+                    }
+                    planner.apply(&updates).unwrap();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn update_batches_parse_and_validate() {
+        let grid = Grid::new(4, 4, Cells::U8(vec![1; 16]));
+        let batches = parse_updates("1 2 FF\n3 3 XX\n\n\n0 1 0\n", &grid).unwrap();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(
+            batches[0],
+            vec![
+                CellUpdate {
+                    position: (1, 2),
+                    value: Some(0xFF)
+                },
+                CellUpdate {
+                    position: (3, 3),
+                    value: None
+                },
+            ]
+        );
+        assert!(parse_updates("4 0 10\n", &grid).is_err());
+        assert!(parse_updates("0 0 100\n", &grid).is_err());
+        assert!(parse_updates("0 0\n", &grid).is_err());
+
+        let mut planner = IncrementalPlanner::new(grid, (0, 0), (3, 3), ortho()).unwrap();
+        assert_eq!(
+            planner.apply(&batches[0]).unwrap_err(),
+            SolveError::Wall((3, 3))
+        );
+        assert_eq!(planner.grid().get(1, 2), 1);
+    }
+
+    fn brute_force_limited(
+        grid: &Grid,
+        goal: (usize, usize),
+        limit: usize,
+        path: &mut Vec<(usize, usize)>,
+    ) -> Option<u64> {
+        let position = path[path.len() - 1];
+        if position == goal {
+            return Some(path_cost(grid, &ortho().moves, path));
+        }
+        if path.len() > limit {
+            return None;
+        }
+        let mut best: Option<u64> = None;
+        for next in ortho().moves.neighbors(grid, position) {
+            if path.contains(&next) {
+                continue;
+            }
+            path.push(next);
+            if let Some(cost) = brute_force_limited(grid, goal, limit, path) {
+                best = Some(best.map_or(cost, |b| b.min(cost)));
+            }
+            path.pop();
+        }
+        best
+    }
+
+    #[test]
+    fn step_limited_search_matches_brute_force() {
+        for seed in 0..12 {
+            let grid = random_grid(seed, 5, 4, 0.1);
+            for limit in [7, 8, 10, 14] {
+                for heuristic in [Heuristic::Zero, Heuristic::Manhattan] {
+                    let mut opts = options(Topology::Ortho, heuristic, QueueKind::BinaryHeap);
+                    opts.max_steps = Some(limit);
+                    let result = solve_dijkstra(&grid, (0, 0), (4, 3), &opts);
+                    let expected = brute_force_limited(&grid, (4, 3), limit, &mut vec![(0, 0)]);
+                    assert_eq!(
+                        cost(&grid, &opts, &result),
+                        expected,
+                        "seed {} limit {}",
+                        seed,
+                        limit
+                    );
+                    assert!(result.path.len() <= limit + 1);
+                }
+            }
+        }
+
+        let mut opts = ortho();
+        opts.max_steps = Some(15);
+        let grid = random_grid(3, 6, 6, 0.0);
+        let result = solve_route(&grid, &[(0, 0), (5, 0), (0, 5)], &opts, None);
+        assert_eq!(result.path.len(), 16);
+        assert_eq!(result.waypoints, vec![0, 5, 15]);
+        opts.max_steps = Some(14);
+        assert!(
+            solve_route(&grid, &[(0, 0), (5, 0), (0, 5)], &opts, None)
+                .path
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn forbidden_values_and_step_budget_are_reported() {
+        let mut grid = Grid::new(
+            3,
+            3,
+            Cells::U8(vec![0x10, 0xF0, 0x10, 0x10, 0xF0, 0x10, 0x10, 0x10, 0xE0]),
+        );
+        assert_eq!(forbid_values(&mut grid, &[(0xC1, 0xFF)], &[(1, 0)]), 2);
+        assert!(!grid.is_wall(1, 0));
+        assert!(grid.is_wall(1, 1));
+        assert!(grid.is_wall(2, 2));
+        assert_eq!(min_steps(&grid, (0, 0), (2, 0), &ortho().moves), Some(2));
+        assert_eq!(min_steps(&grid, (0, 0), (2, 2), &ortho().moves), None);
+
+        let mut opts = ortho();
+        opts.max_steps = Some(5);
+        let open = Grid::new(4, 4, Cells::U8(vec![1; 16]));
+        let solver = Solver::new(&open, opts.clone(), LongestPathBudget::default()).unwrap();
+        assert_eq!(
+            solver.shortest(&[(0, 0), (3, 3)]).unwrap_err(),
+            SolveError::StepBudget {
+                required: 6,
+                limit: 5
+            }
+        );
+        assert_eq!(solver.shortest(&[(0, 0), (3, 0)]).unwrap().cells.len(), 4);
+        opts.max_steps = Some(6);
+        let solver = Solver::new(&open, opts, LongestPathBudget::default()).unwrap();
+        assert_eq!(solver.shortest(&[(0, 0), (3, 3)]).unwrap().cells.len(), 7);
+    }
+
+    #[test]
+    fn cost_expressions_compile_and_report_errors() {
+        let table = compile_cost_expr("max(to - from, 0) * 2 + 1").unwrap();
+        let mut opts = ortho();
+        opts.moves.cost = CostModel::Table(Arc::new(table));
+        let grid = Grid::new(3, 1, Cells::U8(vec![10, 30, 5]));
+        assert_eq!(path_cost(&grid, &opts.moves, &[(0, 0), (1, 0), (2, 0)]), 42);
+        assert!(compile_cost_expr("to +").is_err());
+        assert!(compile_cost_expr("height").is_err());
+        assert!(compile_cost_expr("from - 300").is_err());
+    }
+
+    #[test]
+    fn generators_are_deterministic_per_seed() {
+        for generator in [
+            Generator::Uniform,
+            Generator::Noise,
+            Generator::Valleys,
+            Generator::Maze,
+            Generator::Gradient,
+        ] {
+            let params = GeneratorParams {
+                generator,
+                scale: 8.0,
+                octaves: 3,
+                count: 3,
+                corridor: 1,
+                angle: 45.0,
+                roughness: 0.2,
+            };
+            let make =
+                || generate_map(16, 12, params, CellWidth::U8, &mut StdRng::seed_from_u64(9));
+            let data = make();
+            assert_eq!(data.len(), 16 * 12);
+            assert_eq!(data, make(), "{:?}", generator);
+        }
+    }
+
+    #[test]
+    fn trace_round_trips_through_text() {
+        let grid = random_grid(8, 6, 6, 0.1);
+        let mut events = Vec::new();
+        solve_route(&grid, &[(0, 0), (5, 5)], &ortho(), Some(&mut events));
+        assert!(matches!(events[0], TraceEvent::Search { .. }));
+        assert_eq!(
+            decode_trace(&encode_trace(&grid, &events), &grid),
+            Ok(events)
+        );
+        assert!(decode_trace("TRACE 1 2 2\n", &grid).is_err());
+    }
+
+    #[test]
+    fn solver_reports_errors_instead_of_panicking() {
+        let mut grid = random_grid(10, 5, 5, 0.0);
+        grid.walls[6] = true;
+        let solver = Solver::new(
+            &grid,
+            SearchOptions::default(),
+            LongestPathBudget::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            solver.shortest(&[(0, 0)]).unwrap_err(),
+            SolveError::InvalidRoute
+        );
+        assert_eq!(solver.flow_field(&[]).unwrap_err(), SolveError::NoSources);
+        assert_eq!(
+            solver.shortest(&[(0, 0), (5, 0)]).unwrap_err(),
+            SolveError::OutOfBounds((5, 0))
+        );
+        assert_eq!(
+            solver.longest(&[(1, 1), (4, 4)]).unwrap_err(),
+            SolveError::Wall((1, 1))
+        );
+
+        let shortest = solver.shortest(&[(0, 0), (4, 4)]).unwrap();
+        assert_eq!(shortest.cells.first(), Some(&(0, 0)));
+        assert_eq!(shortest.cells.last(), Some(&(4, 4)));
+        assert_eq!(
+            shortest.cost,
+            path_cost(&grid, &solver_moves(), &shortest.cells)
+        );
+        let longest = solver.longest(&[(0, 0), (4, 4)]).unwrap();
+        assert!(longest.cost >= shortest.cost);
+        let (alternatives, _) = solver.alternatives((0, 0), (4, 4), 3, 1).unwrap();
+        assert_eq!(alternatives[0].cost, shortest.cost);
+        let field = solver.cost_field((0, 0)).unwrap();
+        assert_eq!(field.dist[24], Some(shortest.cost));
+
+        let mut walled = Grid::new(3, 3, Cells::U8(vec![1; 9]));
+        for y in 0..3 {
+            walled.walls[y * 3 + 1] = true;
+        }
+        let solver = Solver::new(
+            &walled,
+            SearchOptions::default(),
+            LongestPathBudget::default(),
+        )
+        .unwrap();
+        assert!(matches!(
+            solver.shortest(&[(0, 0), (2, 2)]),
+            Err(SolveError::NoPath { expanded }) if expanded > 0
+        ));
+
+        let wide = Grid::new(1, 1, Cells::U32(vec![0]));
+        let mut opts = SearchOptions::default();
+        opts.moves.cost = CostModel::Squared;
+        assert!(matches!(
+            Solver::new(&wide, opts, LongestPathBudget::default()),
+            Err(SolveError::Unsupported(_))
+        ));
+
+        let opts = options(Topology::Diag, Heuristic::Manhattan, QueueKind::BinaryHeap);
+        assert!(matches!(
+            Solver::new(&walled, opts, LongestPathBudget::default()),
+            Err(SolveError::Unsupported(_))
+        ));
+    }
+
+    fn solver_moves() -> Neighborhood {
+        SearchOptions::default().moves
+    }
+}
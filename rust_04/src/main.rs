@@ -1,16 +1,25 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use colored::*;
-use rand::Rng;
-use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashMap};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::style::{self, Attribute, Color as TermColor, ContentStyle};
+use crossterm::{cursor, execute, queue, terminal};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rust_04::*;
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
-    #[arg(required_unless_present = "generate")]
+    #[arg(required_unless_present_any = ["generate", "bench", "batch"])]
     file: Option<String>,
 
     #[arg(short, long)]
@@ -27,354 +36,2336 @@ struct Args {
 
     #[arg(short, long)]
     animate: bool,
+
+    #[arg(short, long, value_enum, default_value_t = Topology::Ortho)]
+    topology: Topology,
+
+    #[arg(long, default_value_t = std::f64::consts::SQRT_2)]
+    diagonal_cost: f64,
+
+    #[arg(long, value_enum, default_value_t = Heuristic::Zero)]
+    heuristic: Heuristic,
+
+    #[arg(long, value_parser = parse_coord)]
+    from: Option<Coord>,
+
+    #[arg(long, value_parser = parse_coord)]
+    to: Option<Coord>,
+
+    #[arg(long, value_parser = parse_coord)]
+    via: Vec<Coord>,
+
+    #[arg(long)]
+    mask: Option<String>,
+
+    #[arg(long, default_value_t = 0.0)]
+    obstacles: f64,
+
+    #[arg(long, default_value_t = 100)]
+    max_exact_cells: usize,
+
+    #[arg(long, default_value_t = 2000)]
+    max_time_ms: u64,
+
+    #[arg(long, value_enum, default_value_t = MapFormat::Text)]
+    map_format: MapFormat,
+
+    #[arg(long, conflicts_with = "generate")]
+    convert: Option<String>,
+
+    #[arg(long, conflicts_with = "generate")]
+    check: bool,
+
+    #[arg(long, value_enum, default_value_t = Generator::Uniform)]
+    generator: Generator,
+
+    #[arg(long, default_value_t = 8.0)]
+    scale: f64,
+
+    #[arg(long, default_value_t = 4)]
+    octaves: u32,
+
+    #[arg(long, default_value_t = 3)]
+    count: usize,
+
+    #[arg(long, default_value_t = 1)]
+    corridor: usize,
+
+    #[arg(long, default_value_t = 45.0)]
+    angle: f64,
+
+    #[arg(long, default_value_t = 0.2)]
+    roughness: f64,
+
+    #[arg(long)]
+    seed: Option<u64>,
+
+    #[arg(short, long)]
+    export: Option<String>,
+
+    #[arg(long, default_value_t = 16)]
+    cell_size: usize,
+
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    #[arg(short = 'k', long, default_value_t = 1)]
+    alternatives: usize,
+
+    #[arg(long, default_value_t = 0)]
+    min_diff: usize,
+
+    #[arg(long)]
+    cost_field: Option<String>,
+
+    #[arg(long)]
+    pred_field: Option<String>,
+
+    #[arg(long)]
+    heatmap: bool,
+
+    #[arg(long, value_enum, default_value_t = QueueKind::BinaryHeap)]
+    queue: QueueKind,
+
+    #[arg(long, conflicts_with_all = ["file", "generate"])]
+    bench: Option<usize>,
+
+    #[arg(long)]
+    tui: bool,
+
+    #[arg(long)]
+    trace: Option<String>,
+
+    #[arg(long, requires = "file")]
+    replay: Option<String>,
+
+    #[arg(long, default_value_t = 50)]
+    replay_delay: u64,
+
+    #[arg(long, requires = "replay")]
+    frames: Option<String>,
+
+    #[arg(long, conflicts_with = "trace")]
+    bidirectional: bool,
+
+    #[arg(long, value_enum, default_value_t = CostKind::Entered)]
+    cost_model: CostKind,
+
+    #[arg(long, default_value_t = 1)]
+    step_cost: u32,
+
+    #[arg(long)]
+    cost_expr: Option<String>,
+
+    #[arg(long, value_enum, default_value_t = CellWidth::U8)]
+    cell_width: CellWidth,
+
+    #[arg(long, conflicts_with_all = ["file", "generate", "bench"])]
+    batch: Option<String>,
+
+    #[arg(long, requires = "batch")]
+    summary: Option<String>,
+
+    #[arg(long)]
+    threads: Option<usize>,
+
+    #[arg(long, value_parser = parse_coord, conflicts_with = "sources")]
+    flow_to: Vec<Coord>,
+
+    #[arg(long, requires = "flow_to")]
+    flow_out: Option<String>,
+
+    #[arg(long, value_parser = parse_coord)]
+    sources: Vec<Coord>,
+
+    #[arg(long, requires = "sources")]
+    regions_out: Option<String>,
+
+    #[arg(long, requires = "file", conflicts_with_all = ["via", "tui", "replay"])]
+    updates: Option<String>,
+
+    #[arg(long, conflicts_with_all = ["both", "bidirectional", "tui", "trace", "animate", "updates"])]
+    max_steps: Option<usize>,
+
+    #[arg(long, value_parser = parse_value_range)]
+    forbid: Vec<(u32, u32)>,
+
+    #[arg(long)]
+    wrap: bool,
 }
 
-#[derive(Copy, Clone, Eq, PartialEq)]
-struct State {
-    cost: u32,
-    position: (usize, usize),
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+struct Coord {
+    x: usize,
+    y: usize,
+    layer: usize,
 }
 
-impl Ord for State {
-    fn cmp(&self, other: &Self) -> Ordering {
-        other
-            .cost
-            .cmp(&self.cost)
-            .then_with(|| self.position.0.cmp(&other.position.0))
-            .then_with(|| self.position.1.cmp(&other.position.1))
+impl Coord {
+    fn resolve(self, grid: &Grid) -> Result<(usize, usize), String> {
+        if self.x >= grid.width || self.y >= grid.layer_height() || self.layer >= grid.layers {
+            let (label, size) = if grid.layers > 1 {
+                (
+                    format!("({},{},{})", self.x, self.y, self.layer),
+                    format!("{}x{}x{}", grid.width, grid.layer_height(), grid.layers),
+                )
+            } else {
+                (
+                    format!("({},{})", self.x, self.y),
+                    format!("{}x{}", grid.width, grid.height),
+                )
+            };
+            return Err(format!("Coordonnée {} hors de la grille {}", label, size));
+        }
+        Ok(grid.position(self.x, self.y, self.layer))
     }
 }
 
-impl PartialOrd for State {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
 }
 
-struct Grid {
-    width: usize,
-    height: usize,
-    data: Vec<u8>,
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum CostKind {
+    Entered,
+    Slope,
+    Squared,
+    Step,
+    Expr,
 }
 
-impl Grid {
-    fn get(&self, x: usize, y: usize) -> u8 {
-        self.data[y * self.width + x]
+fn parse_value_range(s: &str) -> Result<(u32, u32), String> {
+    let hex = |v: &str| {
+        let v = v.trim();
+        u32::from_str_radix(v.strip_prefix("0x").unwrap_or(v), 16)
+            .map_err(|_| format!("Valeur hexadécimale invalide: {}", v))
+    };
+    let (low, high) = match s.split_once('-') {
+        Some((low, high)) => (hex(low)?, hex(high)?),
+        None => (hex(s)?, hex(s)?),
+    };
+    if low > high {
+        return Err(format!(
+            "Intervalle vide '{}'. Utilisez min-max (ex: C1-FF)",
+            s
+        ));
     }
+    Ok((low, high))
+}
+
+fn parse_coord(s: &str) -> Result<Coord, String> {
+    let parts: Vec<&str> = s.split(',').collect();
+    let (x, y, layer) = match parts[..] {
+        [x, y] => (x, y, None),
+        [x, y, layer] => (x, y, Some(layer)),
+        _ => {
+            return Err(format!(
+                "Format invalide '{}'. Utilisez x,y ou x,y,z (ex: 3,4)",
+                s
+            ));
+        }
+    };
+    let x = x
+        .trim()
+        .parse()
+        .map_err(|_| format!("Abscisse invalide: {}", x))?;
+    let y = y
+        .trim()
+        .parse()
+        .map_err(|_| format!("Ordonnée invalide: {}", y))?;
+    let layer = match layer {
+        Some(layer) => layer
+            .trim()
+            .parse()
+            .map_err(|_| format!("Couche invalide: {}", layer))?,
+        None => 0,
+    };
+    Ok(Coord { x, y, layer })
 }
 
 fn main() {
     let args = Args::parse();
 
-    let grid = if let Some(size_str) = args.generate {
-        let parts: Vec<&str> = size_str.split('x').collect();
-        if parts.len() != 2 {
-            eprintln!("Format invalide. Utilisez LxH (ex: 12x8)");
-            return;
+    if args.diagonal_cost.is_nan() || args.diagonal_cost < 0.0 {
+        eprintln!("Coût diagonal invalide: {}", args.diagonal_cost);
+        process::exit(1);
+    }
+    if args.alternatives > 1 && !args.via.is_empty() {
+        eprintln!("Les chemins alternatifs ne supportent pas --via");
+        process::exit(1);
+    }
+    if args.alternatives > 1 && args.max_steps.is_some() {
+        eprintln!("Les chemins alternatifs ne supportent pas --max-steps");
+        process::exit(1);
+    }
+    let cost = match (args.cost_model, &args.cost_expr) {
+        (CostKind::Expr, Some(source)) => match compile_cost_expr(source) {
+            Ok(table) => CostModel::Table(Arc::new(table)),
+            Err(e) => {
+                eprintln!("Expression de coût invalide: {}", e);
+                process::exit(1);
+            }
+        },
+        (CostKind::Expr, None) => {
+            eprintln!("--cost-model expr nécessite --cost-expr");
+            process::exit(1);
+        }
+        (_, Some(_)) => {
+            eprintln!("--cost-expr nécessite --cost-model expr");
+            process::exit(1);
         }
-        let w: usize = parts[0].parse().expect("Largeur invalide");
-        let h: usize = parts[1].parse().expect("Hauteur invalide");
+        (CostKind::Entered, None) => CostModel::Entered,
+        (CostKind::Slope, None) => CostModel::Slope,
+        (CostKind::Squared, None) => CostModel::Squared,
+        (CostKind::Step, None) => CostModel::Step(args.step_cost),
+    };
+    let moves = Neighborhood {
+        topology: args.topology,
+        diagonal_cost: args.diagonal_cost,
+        cost,
+        wrap: args.wrap,
+    };
+    if let Some(max_side) = args.bench {
+        run_benchmark(
+            max_side,
+            moves,
+            args.heuristic,
+            args.bidirectional,
+            args.seed.unwrap_or(0),
+        );
+        return;
+    }
 
-        println!("Generating {}x{} hexadecimal grid...", w, h);
-        let map = generate_map(w, h);
+    if let Some(pattern) = &args.batch {
+        run_batch(&args, moves, pattern);
+        return;
+    }
 
-        if let Some(path) = &args.output {
-            save_map(&map, w, h, path);
-            println!("Map saved to: {}", path);
+    let text = args.format == OutputFormat::Text;
+    let seed = args.seed.unwrap_or_else(|| rand::rng().random());
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let mut grid = if let Some(size_str) = &args.generate {
+        let parts: Vec<&str> = size_str.split('x').collect();
+        if !matches!(parts.len(), 2 | 3) {
+            eprintln!("Format invalide. Utilisez LxH ou LxHxP (ex: 12x8 ou 12x8x3)");
+            process::exit(1);
+        }
+        let dimension = |value: &str, name: &str| -> usize {
+            value.parse().unwrap_or_else(|_| {
+                eprintln!("{} invalide: {}", name, value);
+                process::exit(1);
+            })
+        };
+        let w = dimension(parts[0], "Largeur");
+        let h = dimension(parts[1], "Hauteur");
+        let d = parts.get(2).map_or(1, |d| dimension(d, "Profondeur"));
+        if w == 0 || h == 0 || d == 0 {
+            eprintln!("Dimensions invalides: {}", size_str);
+            process::exit(1);
         }
+        if args.scale.is_nan() || args.scale <= 0.0 || !(0.0..=1.0).contains(&args.roughness) {
+            eprintln!("Paramètres de génération invalides");
+            process::exit(1);
+        }
+        let params = GeneratorParams {
+            generator: args.generator,
+            scale: args.scale,
+            octaves: args.octaves.max(1),
+            count: args.count,
+            corridor: args.corridor.max(1),
+            angle: args.angle,
+            roughness: args.roughness,
+        };
 
-        if args.visualize {
-            print_colored_grid(&map, w, h, &[], false);
+        if text {
+            println!(
+                "Generating {} hexadecimal grid ({:?}, seed {})...",
+                size_str, params.generator, seed
+            );
+        }
+        let mut data = Cells::new(args.cell_width);
+        for _ in 0..d {
+            data.extend(generate_map(w, h, params, args.cell_width, &mut rng).iter());
+        }
+        let mut grid = Grid::new(w, h * d, data);
+        grid.seed = Some(seed);
+        grid.layers = d;
+        if d > 1 {
+            for _ in 0..(w * h / 32).max(1) {
+                let (x, y) = (rng.random_range(0..w), rng.random_range(0..h));
+                for layer in 0..d {
+                    let (x, y) = grid.position(x, y, layer);
+                    grid.stairs[y * w + x] = true;
+                }
+            }
+        }
+        grid
+    } else if let Some(path) = &args.file {
+        if text && !args.check {
+            println!("Analyzing hexadecimal grid...");
+        }
+        match load_map(path) {
+            Ok(grid) => grid,
+            Err(errors) => {
+                for e in &errors {
+                    eprintln!("{}: {}", path, e);
+                }
+                eprintln!("{} erreur(s) dans la carte", errors.len());
+                process::exit(1);
+            }
+        }
+    } else {
+        return;
+    };
+
+    if let Some(mask_path) = &args.mask
+        && let Err(e) = apply_mask(&mut grid, mask_path)
+    {
+        eprintln!("Erreur masque: {}", e);
+        process::exit(1);
+    }
+
+    let opts = SearchOptions {
+        moves: moves.clone(),
+        heuristic: args.heuristic,
+        maximize: false,
+        queue: args.queue,
+        bidirectional: args.bidirectional,
+        max_steps: args.max_steps,
+    };
+    if let Err(e) = check_cost_support(&opts, &grid) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+
+    if args.check {
+        if let Some(seed) = grid.seed {
+            println!("Seed: {}", seed);
+        }
+        if grid.layers > 1 {
+            println!(
+                "{}: OK ({}x{}x{}, {} wall(s), {} stair(s))",
+                args.file.as_deref().unwrap_or_default(),
+                grid.width,
+                grid.layer_height(),
+                grid.layers,
+                grid.walls.iter().filter(|&&wall| wall).count(),
+                grid.stairs.iter().filter(|&&stair| stair).count()
+            );
         } else {
-            print_raw_grid(&map, w, h);
+            println!(
+                "{}: OK ({}x{}, {} wall(s))",
+                args.file.as_deref().unwrap_or_default(),
+                grid.width,
+                grid.height,
+                grid.walls.iter().filter(|&&wall| wall).count()
+            );
         }
+        return;
+    }
 
-        Grid {
-            width: w,
-            height: h,
-            data: map,
+    if let Some(out) = &args.convert {
+        if let Err(e) = save_map(&grid, out, args.map_format) {
+            eprintln!("{}", e);
+            process::exit(1);
         }
-    } else if let Some(path) = args.file {
-        println!("Analyzing hexadecimal grid...");
-        let (map, w, h) = load_map(&path);
+        println!(
+            "Map converted to {:?}: {} ({}x{})",
+            args.map_format, out, grid.width, grid.height
+        );
+        return;
+    }
 
-        if args.visualize {
-            println!("HEXADECIMAL GRID (rainbow gradient):");
-            println!("======================================");
-            print_colored_grid(&map, w, h, &[], false);
-            return;
+    if let Some(trace_path) = &args.replay {
+        let events = match fs::read_to_string(trace_path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| decode_trace(&content, &grid))
+        {
+            Ok(events) => events,
+            Err(e) => {
+                eprintln!("Erreur trace {}: {}", trace_path, e);
+                process::exit(1);
+            }
+        };
+        match replay_trace(
+            &grid,
+            &events,
+            "Replaying search trace...",
+            1,
+            args.replay_delay,
+            args.frames.as_deref(),
+        ) {
+            Ok(frames) if args.frames.is_some() => {
+                println!(
+                    "{} frame(s) written to {}",
+                    frames,
+                    args.frames.as_deref().unwrap()
+                );
+            }
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("Erreur écriture frames: {}", e);
+                process::exit(1);
+            }
         }
+        return;
+    }
 
-        Grid {
-            width: w,
-            height: h,
-            data: map,
+    let route = build_route(&args, &grid).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+
+    if args.generate.is_some() {
+        if !(0.0..1.0).contains(&args.obstacles) {
+            eprintln!("Densité d'obstacles invalide: {}", args.obstacles);
+            process::exit(1);
         }
-    } else {
+        if args.obstacles > 0.0 {
+            sprinkle_obstacles(&mut grid, args.obstacles, &route, &mut rng);
+        }
+
+        if let Some(path) = &args.output {
+            if let Err(e) = save_map(&grid, path, args.map_format) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+            if text {
+                println!("Map saved to: {}", path);
+            }
+        }
+
+        if text && args.visualize {
+            print_colored_grid(&grid, &[], false, moves.topology);
+        } else if text {
+            print_raw_grid(&grid);
+        }
+    } else if args.visualize {
+        println!("HEXADECIMAL GRID (rainbow gradient):");
+        println!("======================================");
+        print_colored_grid(&grid, &[], false, moves.topology);
         return;
+    }
+
+    let budget = LongestPathBudget {
+        max_cells: args.max_exact_cells,
+        time_limit: Duration::from_millis(args.max_time_ms),
     };
 
-    println!("\nMINIMUM COST PATH:");
-    println!("==================");
-    let min_path = solve_dijkstra(&grid, false, args.animate);
-    print_path_result(&grid, &min_path, "minimum");
+    let unfiltered = grid.clone();
+    if !args.flow_to.is_empty() || !args.sources.is_empty() {
+        let seeds = [args.flow_to.as_slice(), args.sources.as_slice()]
+            .concat()
+            .into_iter()
+            .map(|coord| coord.resolve(&grid))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(1);
+            });
+        filter_values(&args, &mut grid, &seeds, text);
+        run_field_mode(&args, &grid, &seeds, &opts, budget);
+        return;
+    }
 
-    if args.both {
-        println!("\nMAXIMUM COST PATH:");
-        println!("==================");
-        let max_path = solve_dijkstra(&grid, true, false);
-        print_path_result(&grid, &max_path, "maximum");
+    for &(x, y) in &route {
+        if grid.is_wall(x, y) {
+            eprintln!("La cellule {} est un mur", grid.label((x, y)));
+            process::exit(1);
+        }
     }
-}
+    filter_values(&args, &mut grid, &route[..1], text);
 
-fn solve_dijkstra(grid: &Grid, maximize: bool, animate: bool) -> Vec<(usize, usize)> {
-    let start = (0, 0);
-    let end = (grid.width - 1, grid.height - 1);
+    if args.tui {
+        let goal = route[route.len() - 1];
+        if let Err(e) = run_tui(grid, route[0], goal, opts, budget) {
+            eprintln!("Erreur terminal: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
 
-    let mut dist: HashMap<(usize, usize), u32> = HashMap::new();
-    let mut came_from: HashMap<(usize, usize), (usize, usize)> = HashMap::new();
-    let mut heap = BinaryHeap::new();
+    if let Some(updates_path) = &args.updates {
+        run_updates(updates_path, grid, route[0], route[1], &opts, text);
+        return;
+    }
 
-    dist.insert(start, 0);
-    heap.push(State {
-        cost: 0,
-        position: start,
+    if text {
+        println!("\nMINIMUM COST PATH:");
+        println!("==================");
+    }
+    let solver = Solver::new(&grid, opts.clone(), budget).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
     });
+    let timer = Instant::now();
+    let animate = args.animate && text;
+    let mut events = Vec::new();
+    let min_path = if args.trace.is_some() || animate {
+        solver.shortest_traced(&route, &mut events)
+    } else {
+        solver.shortest(&route)
+    };
+    let min_elapsed = timer.elapsed();
+    if animate {
+        let _ = replay_trace(
+            &grid,
+            &events,
+            "Searching for minimum cost path...",
+            5,
+            50,
+            None,
+        );
+    }
+    if let Some(out) = &args.trace
+        && let Err(e) = fs::write(out, encode_trace(&grid, &events))
+    {
+        eprintln!("Erreur écriture {}: {}", out, e);
+        process::exit(1);
+    }
+    let infeasible = min_path
+        .as_ref()
+        .err()
+        .map(|e| explain_no_path(&grid, &unfiltered, &route, &opts, e));
+    if text {
+        match &infeasible {
+            Some(reason) => println!("No feasible path: {}", reason),
+            None => print_path_result(&grid, moves.topology, min_path.as_ref().ok(), "minimum"),
+        }
+    }
 
-    let mut steps = 0;
+    let alternatives = if args.alternatives > 1 {
+        if text {
+            println!("\n{} SHORTEST ALTERNATIVE PATHS:", args.alternatives);
+            println!("=================================");
+        }
+        let found = match solver.alternatives(route[0], route[1], args.alternatives, args.min_diff)
+        {
+            Ok((found, stats)) => {
+                if text {
+                    println!("Expanded nodes: {}", stats.expanded);
+                }
+                found
+            }
+            Err(_) => vec![],
+        };
+        if text {
+            print_alternatives(&found, &grid, moves.topology);
+        }
+        Some(found)
+    } else {
+        None
+    };
 
-    if animate {
-        print!("\x1B[2J");
+    if args.cost_field.is_some() || args.pred_field.is_some() || args.heatmap {
+        let field = solver.cost_field(route[0]).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        });
+        let goal = route[route.len() - 1];
+
+        if let Some(out) = &args.cost_field
+            && let Err(e) = fs::write(out, encode_cost_field(&field, out.ends_with(".json")))
+        {
+            eprintln!("Erreur écriture {}: {}", out, e);
+            process::exit(1);
+        }
+        if let Some(out) = &args.pred_field
+            && let Err(e) = fs::write(out, encode_pred_field(&field, out.ends_with(".json")))
+        {
+            eprintln!("Erreur écriture {}: {}", out, e);
+            process::exit(1);
+        }
+
+        if text && args.heatmap {
+            println!(
+                "\nCOST FIELD FROM ({},{}) (heat map):",
+                route[0].0, route[0].1
+            );
+            println!("=====================================");
+            print_heatmap(&grid, &field, &field.path_to(goal), moves.topology);
+        }
     }
 
-    while let Some(State { cost, position }) = heap.pop() {
-        steps += 1;
+    let max_path = if args.both {
+        if text {
+            println!("\nMAXIMUM COST PATH:");
+            println!("==================");
+        }
+        let timer = Instant::now();
+        let max_path = solver.longest(&route);
+        let max_elapsed = timer.elapsed();
+        if text {
+            print_path_result(&grid, moves.topology, max_path.as_ref().ok(), "maximum");
+        }
+        Some((max_path, max_elapsed))
+    } else {
+        None
+    };
 
-        if animate && steps % 5 == 0 {
-            print!("\x1B[1;1H");
-            println!("Searching for minimum cost path...\n");
-            println!("Step {}: Exploring {:?} - cost: {}", steps, position, cost);
-            print_animated_grid(grid, &dist, position);
-            thread::sleep(Duration::from_millis(50));
+    if !text {
+        let max_json = max_path
+            .as_ref()
+            .map_or("null".to_string(), |(result, elapsed)| {
+                json_result(result, *elapsed)
+            });
+        println!("{{");
+        println!(
+            "  \"grid\": {{\"width\": {}, \"height\": {}, \"layers\": {}, \"wrap\": {}, \"walls\": {}, \"seed\": {}}},",
+            grid.width,
+            grid.height,
+            grid.layers,
+            moves.wrap,
+            grid.walls.iter().filter(|&&wall| wall).count(),
+            grid.seed
+                .map_or("null".to_string(), |seed| seed.to_string())
+        );
+        println!(
+            "  \"topology\": \"{}\",",
+            moves.topology.to_possible_value().unwrap().get_name()
+        );
+        println!(
+            "  \"cell_width\": \"{}\",",
+            grid.cell_width().to_possible_value().unwrap().get_name()
+        );
+        println!(
+            "  \"cost_model\": \"{}\",",
+            args.cost_model.to_possible_value().unwrap().get_name()
+        );
+        println!("  \"start\": {},", json_coord(route[0]));
+        println!("  \"goal\": {},", json_coord(route[route.len() - 1]));
+        println!("  \"via\": {},", json_coords(&route[1..route.len() - 1]));
+        println!("  \"minimum\": {},", json_result(&min_path, min_elapsed));
+        println!(
+            "  \"infeasible\": {},",
+            infeasible
+                .as_deref()
+                .map_or("null".to_string(), json_string)
+        );
+        if let Some(found) = &alternatives {
+            let entries: Vec<String> = found
+                .iter()
+                .map(|alt| {
+                    format!(
+                        "{{\"cost\": {}, \"path_length\": {}, \"distinct\": {}, \"path\": {}}}",
+                        alt.cost,
+                        alt.path.len(),
+                        alt.distinct,
+                        json_coords(&alt.path)
+                    )
+                })
+                .collect();
+            println!("  \"alternatives\": [{}],", entries.join(", "));
         }
+        println!("  \"maximum\": {}", max_json);
+        println!("}}");
+    }
 
-        if position == end {
-            if animate {
-                print!("\x1B[1;1H");
-                println!("\nStep {}: Path found!                 \n", steps);
-                print_animated_grid(grid, &dist, position);
-            }
-            return reconstruct_path(came_from, end);
+    if let Some(out) = &args.export {
+        let image = ImageLayout {
+            topology: moves.topology,
+            cell_size: args.cell_size.max(2),
+        };
+        let max = max_path
+            .as_ref()
+            .and_then(|(result, _)| result.as_ref().ok())
+            .map(|result| result.cells.as_slice());
+        let min = min_path.as_ref().map_or(&[][..], |result| &result.cells);
+        if let Err(e) = export_image(&grid, image, min, max, out) {
+            eprintln!("Erreur export {}: {}", out, e);
+            process::exit(1);
+        }
+        if text {
+            println!("\nImage exported to: {}", out);
         }
+    }
+}
 
-        if cost > *dist.get(&position).unwrap_or(&u32::MAX) {
-            continue;
+struct BatchRow {
+    file: String,
+    size: Option<(usize, usize)>,
+    min_path: Option<(u64, usize)>,
+    max_path: Option<(u64, usize)>,
+    elapsed: Duration,
+    error: Option<String>,
+}
+
+fn run_batch(args: &Args, moves: Neighborhood, pattern: &str) {
+    let files = match expand_batch_pattern(pattern) {
+        Ok(files) if !files.is_empty() => files,
+        Ok(_) => {
+            eprintln!("Aucune carte ne correspond à {}", pattern);
+            process::exit(1);
         }
+        Err(e) => {
+            eprintln!("Erreur lecture {}: {}", pattern, e);
+            process::exit(1);
+        }
+    };
+    let threads = args
+        .threads
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+        .clamp(1, files.len());
 
-        let (x, y) = position;
-        let neighbors = [
-            if x > 0 { Some((x - 1, y)) } else { None },
-            if x < grid.width - 1 {
-                Some((x + 1, y))
-            } else {
-                None
-            },
-            if y > 0 { Some((x, y - 1)) } else { None },
-            if y < grid.height - 1 {
-                Some((x, y + 1))
-            } else {
-                None
-            },
-        ];
+    let opts = SearchOptions {
+        moves,
+        heuristic: args.heuristic,
+        maximize: false,
+        queue: args.queue,
+        bidirectional: args.bidirectional,
+        max_steps: args.max_steps,
+    };
+    let budget = LongestPathBudget {
+        max_cells: args.max_exact_cells,
+        time_limit: Duration::from_millis(args.max_time_ms),
+    };
+
+    let next = AtomicUsize::new(0);
+    let rows: Mutex<Vec<Option<BatchRow>>> = Mutex::new((0..files.len()).map(|_| None).collect());
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                loop {
+                    let i = next.fetch_add(1, AtomicOrdering::Relaxed);
+                    let Some(file) = files.get(i) else {
+                        break;
+                    };
+                    let row = solve_batch_map(args, file, &opts, budget);
+                    rows.lock().unwrap()[i] = Some(row);
+                }
+            });
+        }
+    });
+    let rows: Vec<BatchRow> = rows.into_inner().unwrap().into_iter().flatten().collect();
+
+    if let Some(out) = &args.summary {
+        let content = if out.ends_with(".json") {
+            encode_batch_json(&rows)
+        } else {
+            encode_batch_csv(&rows)
+        };
+        if let Err(e) = fs::write(out, content) {
+            eprintln!("Erreur écriture {}: {}", out, e);
+            process::exit(1);
+        }
+    }
+
+    if args.format == OutputFormat::Json {
+        print!("{}", encode_batch_json(&rows));
+    } else {
+        print_batch_summary(&rows, threads);
+        if let Some(out) = &args.summary {
+            println!("Summary written to: {}", out);
+        }
+    }
+}
 
-        for neighbor in neighbors.iter().flatten() {
-            let val = grid.get(neighbor.0, neighbor.1) as u32;
-            let weight = if maximize { 255 - val } else { val };
-            let next_cost = cost + weight;
+fn expand_batch_pattern(pattern: &str) -> Result<Vec<String>, String> {
+    let path = Path::new(pattern);
+    let (dir, name_pattern) = if path.is_dir() {
+        (path, "*")
+    } else {
+        let dir = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("");
+        (dir, name)
+    };
+
+    let entries = fs::read_dir(dir).map_err(|e| e.to_string())?;
+    let mut files: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| wildcard_match(name_pattern, name))
+        })
+        .map(|entry| entry.path().to_string_lossy().into_owned())
+        .collect();
+    files.sort();
+    Ok(files)
+}
 
-            if next_cost < *dist.get(neighbor).unwrap_or(&u32::MAX) {
-                heap.push(State {
-                    cost: next_cost,
-                    position: *neighbor,
-                });
-                dist.insert(*neighbor, next_cost);
-                came_from.insert(*neighbor, position);
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let (pattern, name): (Vec<char>, Vec<char>) =
+        (pattern.chars().collect(), name.chars().collect());
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
             }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
         }
     }
+    pattern[p..].iter().all(|&c| c == '*')
+}
 
-    vec![]
+fn solve_batch_map(
+    args: &Args,
+    file: &str,
+    opts: &SearchOptions,
+    budget: LongestPathBudget,
+) -> BatchRow {
+    let timer = Instant::now();
+    let mut row = BatchRow {
+        file: file.to_string(),
+        size: None,
+        min_path: None,
+        max_path: None,
+        elapsed: Duration::ZERO,
+        error: None,
+    };
+
+    let mut grid = match load_map(file) {
+        Ok(grid) => grid,
+        Err(errors) => {
+            let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            row.error = Some(messages.join("; "));
+            row.elapsed = timer.elapsed();
+            return row;
+        }
+    };
+    row.size = Some((grid.width, grid.height));
+
+    let route = match build_route(args, &grid) {
+        Ok(route) => route,
+        Err(e) => {
+            row.error = Some(e);
+            row.elapsed = timer.elapsed();
+            return row;
+        }
+    };
+    forbid_values(&mut grid, &args.forbid, &route[..1]);
+    let solver = match Solver::new(&grid, opts.clone(), budget) {
+        Ok(solver) => solver,
+        Err(e) => {
+            row.error = Some(e.to_string());
+            row.elapsed = timer.elapsed();
+            return row;
+        }
+    };
+
+    let summarize = |result: Result<PathResult, SolveError>| match result {
+        Ok(path) => Ok(Some((path.cost, path.cells.len()))),
+        Err(SolveError::NoPath { .. }) => Ok(None),
+        Err(e) => Err(e.to_string()),
+    };
+    match summarize(solver.shortest(&route)) {
+        Ok(summary) => row.min_path = summary,
+        Err(e) => {
+            row.error = Some(e);
+            row.elapsed = timer.elapsed();
+            return row;
+        }
+    }
+    if args.both {
+        row.max_path = summarize(solver.longest(&route)).unwrap_or(None);
+    }
+    row.elapsed = timer.elapsed();
+    row
 }
 
-fn reconstruct_path(
-    came_from: HashMap<(usize, usize), (usize, usize)>,
-    current: (usize, usize),
-) -> Vec<(usize, usize)> {
-    let mut path = vec![current];
-    let mut curr = current;
-    while let Some(&prev) = came_from.get(&curr) {
-        path.push(prev);
-        curr = prev;
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn encode_batch_csv(rows: &[BatchRow]) -> String {
+    let mut out =
+        "file,width,height,min_cost,min_length,max_cost,max_length,time_ms,error\n".to_string();
+    for row in rows {
+        let (width, height) = row.size.map_or((String::new(), String::new()), |(w, h)| {
+            (w.to_string(), h.to_string())
+        });
+        let path = |path: Option<(u64, usize)>| {
+            path.map_or((String::new(), String::new()), |(cost, len)| {
+                (cost.to_string(), len.to_string())
+            })
+        };
+        let (min_cost, min_length) = path(row.min_path);
+        let (max_cost, max_length) = path(row.max_path);
+        out.push_str(&format!(
+            "{},{},{},{},{},{},{},{:.3},{}\n",
+            csv_field(&row.file),
+            width,
+            height,
+            min_cost,
+            min_length,
+            max_cost,
+            max_length,
+            row.elapsed.as_secs_f64() * 1000.0,
+            csv_field(row.error.as_deref().unwrap_or(""))
+        ));
     }
-    path.reverse();
-    path
+    out
 }
 
-fn generate_map(w: usize, h: usize) -> Vec<u8> {
-    let mut rng = rand::rng();
-    let mut map = vec![0u8; w * h];
+fn encode_batch_json(rows: &[BatchRow]) -> String {
+    let optional = |value: Option<String>| value.unwrap_or_else(|| "null".to_string());
+    let entries: Vec<String> = rows
+        .iter()
+        .map(|row| {
+            format!(
+                "  {{\"file\": {}, \"width\": {}, \"height\": {}, \"min_cost\": {}, \"min_length\": {}, \"max_cost\": {}, \"max_length\": {}, \"time_ms\": {:.3}, \"error\": {}}}",
+                json_string(&row.file),
+                optional(row.size.map(|(w, _)| w.to_string())),
+                optional(row.size.map(|(_, h)| h.to_string())),
+                optional(row.min_path.map(|(cost, _)| cost.to_string())),
+                optional(row.min_path.map(|(_, len)| len.to_string())),
+                optional(row.max_path.map(|(cost, _)| cost.to_string())),
+                optional(row.max_path.map(|(_, len)| len.to_string())),
+                row.elapsed.as_secs_f64() * 1000.0,
+                optional(row.error.as_deref().map(json_string))
+            )
+        })
+        .collect();
+    format!("[\n{}\n]\n", entries.join(",\n"))
+}
 
-    for cell in &mut map {
-        *cell = rng.random();
+fn print_batch_summary(rows: &[BatchRow], threads: usize) {
+    println!(
+        "BATCH SUMMARY ({} map(s), {} thread(s)):",
+        rows.len(),
+        threads
+    );
+    println!("=========================================");
+    println!(
+        "{:<32} {:>9} {:>12} {:>8} {:>12} {:>8} {:>10}",
+        "file", "size", "min_cost", "min_len", "max_cost", "max_len", "time_ms"
+    );
+    let cell = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    for row in rows {
+        if let Some(error) = &row.error {
+            println!("{:<32} {}", row.file, format!("error: {}", error).red());
+            continue;
+        }
+        println!(
+            "{:<32} {:>9} {:>12} {:>8} {:>12} {:>8} {:>10.2}",
+            row.file,
+            cell(row.size.map(|(w, h)| format!("{}x{}", w, h))),
+            cell(row.min_path.map(|(cost, _)| cost.to_string())),
+            cell(row.min_path.map(|(_, len)| len.to_string())),
+            cell(row.max_path.map(|(cost, _)| cost.to_string())),
+            cell(row.max_path.map(|(_, len)| len.to_string())),
+            row.elapsed.as_secs_f64() * 1000.0
+        );
     }
+    let failed = rows.iter().filter(|row| row.error.is_some()).count();
+    println!("{} solved, {} failed", rows.len() - failed, failed);
+}
 
-    let last_idx = map.len() - 1;
-    map[0] = 0x00;
-    map[last_idx] = 0xFF;
-    map
+fn run_benchmark(
+    max_side: usize,
+    moves: Neighborhood,
+    heuristic: Heuristic,
+    bidirectional: bool,
+    seed: u64,
+) {
+    println!("PATHFINDING BENCHMARK (uniform maps, seed {}):", seed);
+    println!("=============================================");
+    println!(
+        "{:>11} {:>10} {:>16} {:>10} {:>10} {:>12}",
+        "size", "cells", "queue", "time_ms", "expanded", "Mcells/s"
+    );
+
+    let mut side = 64;
+    while side <= max_side.max(64) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let data = generate_uniform(side, side, CellWidth::U8, &mut rng);
+        let grid = Grid::new(side, side, data);
+        let goal = (side - 1, side - 1);
+
+        for queue in [QueueKind::BinaryHeap, QueueKind::Bucket] {
+            let opts = SearchOptions {
+                moves: moves.clone(),
+                heuristic,
+                maximize: false,
+                queue,
+                bidirectional,
+                max_steps: None,
+            };
+            let solver = match Solver::new(&grid, opts, LongestPathBudget::default()) {
+                Ok(solver) => solver,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    continue;
+                }
+            };
+            let timer = Instant::now();
+            let expanded = solver
+                .shortest(&[(0, 0), goal])
+                .map_or(0, |result| result.stats.expanded);
+            let elapsed = timer.elapsed().as_secs_f64();
+            println!(
+                "{:>11} {:>10} {:>16} {:>10.2} {:>10} {:>12.2}",
+                format!("{}x{}", side, side),
+                side * side,
+                format!(
+                    "{}{}",
+                    queue.to_possible_value().unwrap().get_name(),
+                    if bidirectional { "/bidi" } else { "" }
+                ),
+                elapsed * 1000.0,
+                expanded,
+                expanded as f64 / elapsed / 1e6
+            );
+        }
+        side *= 2;
+    }
 }
 
-fn save_map(map: &[u8], w: usize, h: usize, path: &str) {
-    let mut content = String::new();
-    for y in 0..h {
-        for x in 0..w {
-            content.push_str(&format!("{:02X} ", map[y * w + x]));
+fn run_field_mode(
+    args: &Args,
+    grid: &Grid,
+    seeds: &[(usize, usize)],
+    opts: &SearchOptions,
+    budget: LongestPathBudget,
+) {
+    let text = args.format == OutputFormat::Text;
+    let topology = opts.moves.topology;
+    let solver = match Solver::new(grid, opts.clone(), budget) {
+        Ok(solver) => solver,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    let flow = !args.flow_to.is_empty();
+    let timer = Instant::now();
+    let field = if flow {
+        solver.flow_field(seeds)
+    } else {
+        solver.multi_source(seeds)
+    };
+    let elapsed = timer.elapsed();
+    let field = field.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+
+    let encode = |json: bool| {
+        if flow {
+            encode_flow_field(&field, topology, json)
+        } else {
+            encode_regions(&field, json)
         }
-        content.push('\n');
+    };
+    let out = if flow {
+        &args.flow_out
+    } else {
+        &args.regions_out
+    };
+    if let Some(out) = out
+        && let Err(e) = fs::write(out, encode(out.ends_with(".json")))
+    {
+        eprintln!("Erreur écriture {}: {}", out, e);
+        process::exit(1);
+    }
+
+    if !text {
+        print!("{}", encode(true));
+        return;
+    }
+
+    let reached = field.dist.iter().flatten().count();
+    if flow {
+        println!("FLOW FIELD TOWARD {} GOAL(S):", field.sources.len());
+        println!("==============================");
+        print_flow_field(grid, &field, topology);
+    } else {
+        println!("REGIONS FROM {} SOURCE(S):", field.sources.len());
+        println!("==========================");
+        print_regions(grid, &field, topology);
+        for (label, (&(x, y), size)) in field.sources.iter().zip(field.region_sizes()).enumerate() {
+            println!("Source {} ({},{}): {} cell(s)", label, x, y, size);
+        }
+    }
+    println!(
+        "Reached cells: {} / {}",
+        reached,
+        grid.walls.iter().filter(|&&wall| !wall).count()
+    );
+    println!("Time: {:.2}ms", elapsed.as_secs_f64() * 1000.0);
+    if let Some(out) = out {
+        println!("Field saved to: {}", out);
     }
-    fs::write(path, content).expect("Erreur écriture fichier");
 }
 
-fn load_map(path: &str) -> (Vec<u8>, usize, usize) {
-    let content = fs::read_to_string(path).expect("Fichier introuvable");
-    let mut map = Vec::new();
-    let mut width = 0;
-    let mut height = 0;
+fn run_updates(
+    updates_path: &str,
+    grid: Grid,
+    start: (usize, usize),
+    goal: (usize, usize),
+    opts: &SearchOptions,
+    text: bool,
+) {
+    let moves = &opts.moves;
+    let batches = match fs::read_to_string(updates_path)
+        .map_err(|e| e.to_string())
+        .and_then(|content| parse_updates(&content, &grid))
+    {
+        Ok(batches) => batches,
+        Err(e) => {
+            eprintln!("Erreur mises à jour {}: {}", updates_path, e);
+            process::exit(1);
+        }
+    };
+    let mut planner = match IncrementalPlanner::new(grid, start, goal, opts.clone()) {
+        Ok(planner) => planner,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let mut entries = Vec::new();
+    for batch in 0..=batches.len() {
+        let updates = if batch == 0 {
+            &[][..]
+        } else {
+            &batches[batch - 1]
+        };
+        let timer = Instant::now();
+        if let Err(e) = planner.apply(updates) {
+            eprintln!("Lot {}: {}", batch, e);
+            process::exit(1);
+        }
+        let result = planner.plan();
+        let elapsed = timer.elapsed();
 
-    for line in content.lines() {
-        let parts: Vec<u8> = line
-            .split_whitespace()
-            .map(|s| u8::from_str_radix(s, 16).expect("Hex invalide"))
+        if text {
+            if batch == 0 {
+                println!("\nINITIAL PATH:");
+                println!("=============");
+            } else {
+                println!("\nBATCH {} ({} update(s)):", batch, updates.len());
+                println!("=========================");
+            }
+            println!("Time: {:.2}ms", elapsed.as_secs_f64() * 1000.0);
+            print_path_result(
+                planner.grid(),
+                moves.topology,
+                result.as_ref().ok(),
+                "replanned",
+            );
+        } else {
+            entries.push(format!(
+                "{{\"batch\": {}, \"updates\": {}, \"result\": {}}}",
+                batch,
+                updates.len(),
+                json_result(&result, elapsed)
+            ));
+        }
+    }
+
+    if !text {
+        println!("{{");
+        println!("  \"start\": {},", json_coord(start));
+        println!("  \"goal\": {},", json_coord(goal));
+        println!("  \"batches\": [\n    {}\n  ]", entries.join(",\n    "));
+        println!("}}");
+    }
+}
+
+fn filter_values(args: &Args, grid: &mut Grid, keep: &[(usize, usize)], text: bool) {
+    if args.forbid.is_empty() {
+        return;
+    }
+    let forbidden = forbid_values(grid, &args.forbid, keep);
+    if text {
+        let ranges: Vec<String> = args
+            .forbid
+            .iter()
+            .map(|&(low, high)| format!("{:X}-{:X}", low, high))
             .collect();
+        println!(
+            "Forbidden values: {} ({} cell(s) excluded)",
+            ranges.join(", "),
+            forbidden
+        );
+    }
+}
 
-        if parts.is_empty() {
-            continue;
+fn explain_no_path(
+    grid: &Grid,
+    unfiltered: &Grid,
+    route: &[(usize, usize)],
+    opts: &SearchOptions,
+    error: &SolveError,
+) -> String {
+    if let SolveError::StepBudget { required, limit } = error {
+        return format!(
+            "the shortest route needs {} moves, more than the limit of {}",
+            required, limit
+        );
+    }
+    let reachable = |grid: &Grid, leg: &[(usize, usize)]| {
+        Solver::new(grid, opts.clone(), LongestPathBudget::default())
+            .and_then(|solver| solver.min_steps(leg))
+            .is_ok_and(|steps| steps.is_some())
+    };
+    for leg in route.windows(2) {
+        let (from, to) = (leg[0], leg[1]);
+        if grid.is_wall(to.0, to.1) && !unfiltered.is_wall(to.0, to.1) {
+            return format!(
+                "{} has the forbidden value 0x{}",
+                grid.label(to),
+                grid.hex(to.0, to.1)
+            );
         }
-        if width == 0 {
-            width = parts.len();
+        if !reachable(grid, leg) {
+            return if reachable(unfiltered, leg) {
+                format!(
+                    "every route from {} to {} enters a forbidden cell",
+                    grid.label(from),
+                    grid.label(to)
+                )
+            } else {
+                format!(
+                    "{} is unreachable from {}",
+                    grid.label(to),
+                    grid.label(from)
+                )
+            };
         }
-        height += 1;
-        map.extend(parts);
     }
-    (map, width, height)
+    "no path found".to_string()
+}
+
+fn build_route(args: &Args, grid: &Grid) -> Result<Vec<(usize, usize)>, String> {
+    let start = args.from.unwrap_or_default();
+    let end = args.to.unwrap_or(Coord {
+        x: grid.width.saturating_sub(1),
+        y: grid.layer_height().saturating_sub(1),
+        layer: grid.layers - 1,
+    });
+    let mut route = vec![start];
+    route.extend(&args.via);
+    route.push(end);
+    route.into_iter().map(|coord| coord.resolve(grid)).collect()
+}
+
+fn replay_trace(
+    grid: &Grid,
+    events: &[TraceEvent],
+    title: &str,
+    stride: usize,
+    delay_ms: u64,
+    frames_dir: Option<&str>,
+) -> Result<usize, String> {
+    if let Some(dir) = frames_dir {
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir, e))?;
+    } else {
+        print!("\x1B[2J");
+    }
+
+    let mut dist = vec![u64::MAX; grid.data.len()];
+    let mut steps = 0;
+    let mut frames = 0;
+    for event in events {
+        match *event {
+            TraceEvent::Search { start, .. } => {
+                dist.fill(u64::MAX);
+                dist[start.1 * grid.width + start.0] = 0;
+            }
+            TraceEvent::Relax { to, cost, .. } => dist[to.1 * grid.width + to.0] = cost,
+            TraceEvent::Pop { position, cost } => {
+                steps += 1;
+                if steps % stride != 0 {
+                    continue;
+                }
+                frames += 1;
+                let header = format!("Step {}: Exploring {:?} - cost: {}", steps, position, cost);
+                let frame = animated_frame(grid, &dist, position);
+                if let Some(dir) = frames_dir {
+                    let path = format!("{}/frame_{:05}.txt", dir, frames);
+                    fs::write(&path, format!("{}\n\n{}", header, frame))
+                        .map_err(|e| format!("{}: {}", path, e))?;
+                } else {
+                    print!("\x1B[1;1H");
+                    println!("{}\n", title);
+                    println!("{}", header);
+                    print!("{}", frame);
+                    thread::sleep(Duration::from_millis(delay_ms));
+                }
+            }
+        }
+    }
+    Ok(frames)
 }
 
 fn get_color(val: u8) -> Color {
+    let (r, g, b) = get_rgb(val);
+    Color::TrueColor { r, g, b }
+}
+
+fn get_rgb(val: u8) -> (u8, u8, u8) {
     match val {
-        0..=40 => Color::TrueColor {
-            r: 255,
-            g: val * 5,
-            b: 0,
-        },
+        0..=40 => (255, val * 5, 0),
 
-        41..=128 => Color::TrueColor {
-            r: 255 - (val - 40) * 2,
-            g: 255,
-            b: 0,
-        },
+        41..=128 => (255 - (val - 40) * 2, 255, 0),
 
-        129..=180 => Color::TrueColor {
-            r: 0,
-            g: 255,
-            b: (val - 128) * 4,
-        },
+        129..=180 => (0, 255, (val - 128) * 4),
 
         _ => {
             let r_calc = (val as u16 - 180) * 4;
             let r_final = if r_calc > 255 { 255 } else { r_calc as u8 };
 
-            Color::TrueColor {
-                r: r_final,
-                g: 0,
-                b: 255,
+            (r_final, 0, 255)
+        }
+    }
+}
+
+const WALL_RGB: (u8, u8, u8) = (0x30, 0x30, 0x30);
+const MIN_PATH_RGB: (u8, u8, u8) = (0xFF, 0xFF, 0xFF);
+const MAX_PATH_RGB: (u8, u8, u8) = (0x00, 0x00, 0x00);
+
+#[derive(Copy, Clone)]
+struct ImageLayout {
+    topology: Topology,
+    cell_size: usize,
+}
+
+impl ImageLayout {
+    fn size(&self, grid: &Grid) -> (usize, usize) {
+        let cs = self.cell_size;
+        match self.topology {
+            Topology::HexOddR => (grid.width * cs + cs / 2, grid.height * cs),
+            Topology::HexEvenQ => (grid.width * cs, grid.height * cs + cs / 2),
+            _ => (grid.width * cs, grid.height * cs),
+        }
+    }
+
+    fn origin(&self, (x, y): (usize, usize)) -> (usize, usize) {
+        let cs = self.cell_size;
+        match self.topology {
+            Topology::HexOddR => (x * cs + (y % 2) * cs / 2, y * cs),
+            Topology::HexEvenQ => (x * cs, y * cs + (1 - x % 2) * cs / 2),
+            _ => (x * cs, y * cs),
+        }
+    }
+
+    fn center(&self, position: (usize, usize)) -> (usize, usize) {
+        let (px, py) = self.origin(position);
+        (px + self.cell_size / 2, py + self.cell_size / 2)
+    }
+}
+
+fn export_image(
+    grid: &Grid,
+    layout: ImageLayout,
+    min_path: &[(usize, usize)],
+    max_path: Option<&[(usize, usize)]>,
+    path: &str,
+) -> Result<(), String> {
+    let extension = path.rsplit('.').next().unwrap_or_default().to_lowercase();
+    let bytes = match extension.as_str() {
+        "svg" => render_svg(grid, layout, min_path, max_path).into_bytes(),
+        "ppm" => {
+            let canvas = render_raster(grid, layout, min_path, max_path);
+            let mut bytes = format!("P6\n{} {}\n255\n", canvas.width, canvas.height).into_bytes();
+            bytes.extend(&canvas.pixels);
+            bytes
+        }
+        "png" => {
+            let canvas = render_raster(grid, layout, min_path, max_path);
+            encode_png(canvas.width as u32, canvas.height as u32, &canvas.pixels)
+        }
+        _ => return Err("extension inconnue (svg, ppm ou png attendu)".to_string()),
+    };
+    fs::write(path, bytes).map_err(|e| e.to_string())
+}
+
+fn render_svg(
+    grid: &Grid,
+    layout: ImageLayout,
+    min_path: &[(usize, usize)],
+    max_path: Option<&[(usize, usize)]>,
+) -> String {
+    let (width, height) = layout.size(grid);
+    let cs = layout.cell_size;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">\n",
+        width, height, width, height
+    );
+
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            let (r, g, b) = if grid.is_wall(x, y) {
+                WALL_RGB
+            } else {
+                get_rgb(grid.shade(x, y))
+            };
+            let (px, py) = layout.origin((x, y));
+            svg.push_str(&format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#{:02X}{:02X}{:02X}\"><title>({},{}) {}</title></rect>\n",
+                px,
+                py,
+                cs,
+                cs,
+                r,
+                g,
+                b,
+                x,
+                y,
+                grid.hex(x, y)
+            ));
+        }
+    }
+
+    let paths = max_path
+        .map(|path| (path, MAX_PATH_RGB))
+        .into_iter()
+        .chain([(min_path, MIN_PATH_RGB)]);
+    for (path, (r, g, b)) in paths {
+        if path.is_empty() {
+            continue;
+        }
+        let points: Vec<String> = path
+            .iter()
+            .map(|&position| {
+                let (cx, cy) = layout.center(position);
+                format!("{},{}", cx, cy)
+            })
+            .collect();
+        svg.push_str(&format!(
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"#{:02X}{:02X}{:02X}\" stroke-width=\"{}\" stroke-linecap=\"round\" stroke-linejoin=\"round\"/>\n",
+            points.join(" "),
+            r,
+            g,
+            b,
+            (cs / 4).max(1)
+        ));
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Self {
+        Canvas {
+            width,
+            height,
+            pixels: vec![0xFF; width * height * 3],
+        }
+    }
+
+    fn fill_rect(&mut self, x0: usize, y0: usize, w: usize, h: usize, (r, g, b): (u8, u8, u8)) {
+        for y in y0..(y0 + h).min(self.height) {
+            for x in x0..(x0 + w).min(self.width) {
+                let idx = (y * self.width + x) * 3;
+                self.pixels[idx..idx + 3].copy_from_slice(&[r, g, b]);
             }
         }
     }
+
+    fn draw_line(
+        &mut self,
+        from: (usize, usize),
+        to: (usize, usize),
+        thickness: usize,
+        color: (u8, u8, u8),
+    ) {
+        let steps = from.0.abs_diff(to.0).max(from.1.abs_diff(to.1)).max(1);
+        let half = thickness / 2;
+        for i in 0..=steps {
+            let x = from.0 as f64 + (to.0 as f64 - from.0 as f64) * i as f64 / steps as f64;
+            let y = from.1 as f64 + (to.1 as f64 - from.1 as f64) * i as f64 / steps as f64;
+            let (x, y) = (x.round() as usize, y.round() as usize);
+            self.fill_rect(
+                x.saturating_sub(half),
+                y.saturating_sub(half),
+                thickness,
+                thickness,
+                color,
+            );
+        }
+    }
 }
 
-fn print_colored_grid(map: &[u8], w: usize, h: usize, path: &[(usize, usize)], is_anim: bool) {
-    let path_set: HashMap<_, _> = path.iter().map(|&p| (p, true)).collect();
+fn render_raster(
+    grid: &Grid,
+    layout: ImageLayout,
+    min_path: &[(usize, usize)],
+    max_path: Option<&[(usize, usize)]>,
+) -> Canvas {
+    let (width, height) = layout.size(grid);
+    let cs = layout.cell_size;
+    let mut canvas = Canvas::new(width, height);
 
-    for y in 0..h {
-        for x in 0..w {
-            let val = map[y * w + x];
-            let s = format!("{:02X}", val);
-
-            if path_set.contains_key(&(x, y)) {
-                print!("{} ", s.white().on_black().bold());
-            } else if is_anim {
-                print!("{} ", "[]".dimmed());
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            let color = if grid.is_wall(x, y) {
+                WALL_RGB
+            } else {
+                get_rgb(grid.shade(x, y))
+            };
+            let (px, py) = layout.origin((x, y));
+            canvas.fill_rect(px, py, cs, cs, color);
+        }
+    }
+
+    let paths = max_path
+        .map(|path| (path, MAX_PATH_RGB))
+        .into_iter()
+        .chain([(min_path, MIN_PATH_RGB)]);
+    for (path, color) in paths {
+        let thickness = (cs / 4).max(1);
+        for step in path.windows(2) {
+            canvas.draw_line(
+                layout.center(step[0]),
+                layout.center(step[1]),
+                thickness,
+                color,
+            );
+        }
+        for &position in path {
+            let (cx, cy) = layout.center(position);
+            let marker = (cs / 2).max(1);
+            canvas.fill_rect(cx - marker / 2, cy - marker / 2, marker, marker, color);
+        }
+    }
+
+    canvas
+}
+
+fn encode_png(width: u32, height: u32, rgb: &[u8]) -> Vec<u8> {
+    let row_len = width as usize * 3;
+    let mut raw = Vec::with_capacity((row_len + 1) * height as usize);
+    for row in rgb.chunks(row_len) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut zlib = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = raw.chunks(0xFFFF).collect();
+    for (i, block) in blocks.iter().enumerate() {
+        let len = block.len() as u16;
+        zlib.push((i + 1 == blocks.len()) as u8);
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in &raw {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    zlib.extend_from_slice(&((b << 16) | a).to_be_bytes());
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&width.to_be_bytes());
+    ihdr.extend_from_slice(&height.to_be_bytes());
+    ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    for (kind, data) in [(b"IHDR", ihdr), (b"IDAT", zlib), (b"IEND", Vec::new())] {
+        png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        let start = png.len();
+        png.extend_from_slice(kind);
+        png.extend_from_slice(&data);
+        let crc = crc32(&png[start..]);
+        png.extend_from_slice(&crc.to_be_bytes());
+    }
+    png
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
             } else {
-                print!("{} ", s.color(get_color(val)));
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn print_colored_grid(grid: &Grid, paths: &[&[(usize, usize)]], is_anim: bool, topology: Topology) {
+    let mut path_set: HashMap<(usize, usize), usize> = HashMap::new();
+    for (rank, path) in paths.iter().enumerate() {
+        for &p in path.iter() {
+            path_set.entry(p).or_insert(rank);
+        }
+    }
+    let digits = grid.cell_width().digits();
+    let print_cell = |x: usize, y: usize| {
+        let s = grid.hex(x, y);
+
+        let s = if grid.is_stair(x, y) {
+            s.underline()
+        } else {
+            s.normal()
+        };
+
+        if grid.is_wall(x, y) {
+            print!("{} ", "X".repeat(digits).bright_black());
+        } else if let Some(&rank) = path_set.get(&(x, y)) {
+            let background = PATH_BACKGROUNDS[rank % PATH_BACKGROUNDS.len()];
+            print!("{} ", s.white().on_color(background).bold());
+        } else if is_anim {
+            print!(
+                "{} ",
+                format!("[{:width$}]", "", width = digits - 2).dimmed()
+            );
+        } else {
+            print!("{} ", s.color(get_color(grid.shade(x, y))));
+        }
+    };
+    print_layout(grid, digits, topology, print_cell);
+}
+
+fn print_layout(grid: &Grid, digits: usize, topology: Topology, print_cell: impl Fn(usize, usize)) {
+    let (w, h) = (grid.width, grid.height);
+    let pad = " ".repeat(digits + 1);
+
+    for y in 0..h {
+        if grid.layers > 1 && y % grid.layer_height() == 0 {
+            if y > 0 {
+                println!();
+            }
+            println!("Layer {}:", y / grid.layer_height());
+        }
+        match topology {
+            Topology::Ortho | Topology::Diag => {
+                for x in 0..w {
+                    print_cell(x, y);
+                }
+            }
+            Topology::HexOddR => {
+                if y % 2 == 1 {
+                    print!("{}", &pad[..digits / 2 + 1]);
+                }
+                for x in 0..w {
+                    print_cell(x, y);
+                    print!(" ");
+                }
+            }
+            Topology::HexEvenQ => {
+                for x in 0..w {
+                    if x % 2 == 1 {
+                        print_cell(x, y);
+                    } else {
+                        print!("{}", pad);
+                    }
+                }
+                println!();
+                for x in 0..w {
+                    if x % 2 == 0 {
+                        print_cell(x, y);
+                    } else {
+                        print!("{}", pad);
+                    }
+                }
             }
         }
         println!();
     }
 }
 
-fn print_raw_grid(map: &[u8], w: usize, h: usize) {
-    for y in 0..h {
-        for x in 0..w {
-            print!("{:02X} ", map[y * w + x]);
+fn print_flow_field(grid: &Grid, field: &CostField, topology: Topology) {
+    let digits = grid.cell_width().digits();
+    let print_cell = |x: usize, y: usize| {
+        let idx = y * grid.width + x;
+        if grid.is_wall(x, y) {
+            print!("{} ", "X".repeat(digits).bright_black());
+            return;
+        }
+        let label = match (field.dist[idx], field.prev[idx]) {
+            (None, _) => "·".to_string(),
+            (Some(_), None) => "*".to_string(),
+            (Some(_), Some(next)) => flow_arrow(field, topology, (x, y), next).to_string(),
+        };
+        let s = format!("{:^width$}", label, width = digits);
+        if field.prev[idx].is_none() && field.dist[idx].is_some() {
+            print!("{} ", s.white().on_color(Color::Black).bold());
+        } else {
+            print!("{} ", s.color(get_color(grid.shade(x, y))));
+        }
+    };
+    print_layout(grid, digits, topology, print_cell);
+}
+
+fn print_regions(grid: &Grid, field: &CostField, topology: Topology) {
+    let digits = grid
+        .cell_width()
+        .digits()
+        .max(field.sources.len().saturating_sub(1).to_string().len());
+    let print_cell = |x: usize, y: usize| {
+        let idx = y * grid.width + x;
+        if grid.is_wall(x, y) {
+            print!("{} ", "X".repeat(digits).bright_black());
+            return;
+        }
+        match field.owner[idx] {
+            None => print!("{} ", format!("{:^width$}", "·", width = digits).dimmed()),
+            Some(owner) => {
+                let background = REGION_BACKGROUNDS[owner % REGION_BACKGROUNDS.len()];
+                let s = format!("{:>width$}", owner, width = digits)
+                    .white()
+                    .on_color(background);
+                if field.sources.contains(&(x, y)) {
+                    print!("{} ", s.bold().underline());
+                } else {
+                    print!("{} ", s);
+                }
+            }
+        }
+    };
+    print_layout(grid, digits, topology, print_cell);
+}
+
+const REGION_BACKGROUNDS: [Color; 6] = [
+    Color::Blue,
+    Color::Magenta,
+    Color::Red,
+    Color::Cyan,
+    Color::Green,
+    Color::Yellow,
+];
+
+const PATH_BACKGROUNDS: [Color; 6] = [
+    Color::Black,
+    Color::Blue,
+    Color::Magenta,
+    Color::Red,
+    Color::Cyan,
+    Color::Green,
+];
+
+fn print_heatmap(grid: &Grid, field: &CostField, path: &[(usize, usize)], topology: Topology) {
+    let max = field
+        .dist
+        .iter()
+        .flatten()
+        .max()
+        .copied()
+        .unwrap_or(0)
+        .max(1);
+    let scaled = Grid {
+        width: grid.width,
+        height: grid.height,
+        data: Cells::U8(
+            field
+                .dist
+                .iter()
+                .map(|cost| cost.map_or(0, |c| (c * 255 / max) as u8))
+                .collect(),
+        ),
+        walls: field.dist.iter().map(Option::is_none).collect(),
+        seed: None,
+        layers: grid.layers,
+        stairs: grid.stairs.clone(),
+    };
+    println!(
+        "Max distance: 0x{:X} ({} decimal), scaled to 00-FF",
+        max, max
+    );
+    print_colored_grid(&scaled, &[path], false, topology);
+}
+
+fn print_raw_grid(grid: &Grid) {
+    for y in 0..grid.height {
+        if y > 0 && y % grid.layer_height() == 0 {
+            println!("---");
+        }
+        for x in 0..grid.width {
+            if grid.is_wall(x, y) {
+                print!("{} ", "X".repeat(grid.cell_width().digits()));
+            } else if grid.is_stair(x, y) {
+                print!("{}^ ", grid.hex(x, y));
+            } else {
+                print!("{} ", grid.hex(x, y));
+            }
         }
         println!();
     }
 }
 
-fn print_animated_grid(
-    grid: &Grid,
-    visited: &HashMap<(usize, usize), u32>,
-    current: (usize, usize),
-) {
+fn animated_frame(grid: &Grid, dist: &[u64], current: (usize, usize)) -> String {
+    let mut frame = String::new();
     for y in 0..grid.height {
-        print!("[");
+        frame.push('[');
         for x in 0..grid.width {
             if (x, y) == current {
-                print!("*");
-            } else if visited.contains_key(&(x, y)) {
-                print!("v");
+                frame.push('*');
+            } else if grid.is_wall(x, y) {
+                frame.push('#');
+            } else if dist[y * grid.width + x] != u64::MAX {
+                frame.push('v');
             } else {
-                print!(" ");
+                frame.push(' ');
             }
             if x < grid.width - 1 {
-                print!("][");
+                frame.push_str("][");
             }
         }
-        println!("]");
+        frame.push_str("]\n");
     }
+    frame
 }
 
-fn print_path_result(grid: &Grid, path: &[(usize, usize)], label: &str) {
-    if path.is_empty() {
+fn print_path_result(grid: &Grid, topology: Topology, result: Option<&PathResult>, label: &str) {
+    let Some(result) = result else {
         println!("No path found!");
         return;
+    };
+    let path = &result.cells;
+    let stats = &result.stats;
+
+    println!("Total cost: 0x{:X} ({} decimal)", result.cost, result.cost);
+    println!("Path length: {} steps", path.len());
+    println!("Expanded nodes: {}", stats.expanded);
+    println!(
+        "Search: {}",
+        if stats.exact { "exact" } else { "approximate" }
+    );
+    if !stats.meetings.is_empty() {
+        let meetings: Vec<String> = stats
+            .meetings
+            .iter()
+            .map(|&position| grid.label(position))
+            .collect();
+        println!("Frontiers met at: {}", meetings.join(", "));
     }
 
-    let mut total_cost = 0;
-    for (i, &(x, y)) in path.iter().enumerate() {
-        if i > 0 {
-            total_cost += grid.get(x, y) as u32;
+    if stats.waypoints.len() > 2 {
+        println!("Legs:");
+        for (i, (leg, leg_cost)) in stats
+            .waypoints
+            .windows(2)
+            .zip(&result.leg_costs)
+            .enumerate()
+        {
+            let (from, to) = (path[leg[0]], path[leg[1]]);
+            println!(
+                "  {}. {}->{}: 0x{:X} ({} decimal), {} moves",
+                i + 1,
+                grid.label(from),
+                grid.label(to),
+                leg_cost,
+                leg_cost,
+                leg[1] - leg[0]
+            );
         }
     }
 
-    println!("Total cost: 0x{:X} ({} decimal)", total_cost, total_cost);
-    println!("Path length: {} steps", path.len());
-
     println!("Path:");
-    let path_str: Vec<String> = path.iter().map(|(x, y)| format!("({},{})", x, y)).collect();
+    let path_str: Vec<String> = path.iter().map(|&position| grid.label(position)).collect();
     println!("{}", path_str.join("->"));
 
     println!("\n{} COST PATH (shown in WHITE):", label.to_uppercase());
     println!("================================");
-    print_colored_grid(&grid.data, grid.width, grid.height, path, false);
+    print_colored_grid(grid, &[path], false, topology);
+}
+
+fn print_alternatives(alternatives: &[Alternative], grid: &Grid, topology: Topology) {
+    if alternatives.is_empty() {
+        println!("No path found!");
+        return;
+    }
+
+    for (rank, alt) in alternatives.iter().enumerate() {
+        let background = PATH_BACKGROUNDS[rank % PATH_BACKGROUNDS.len()];
+        println!(
+            "{} cost 0x{:X} ({} decimal), {} steps, {} distinct cells",
+            format!("#{}", rank + 1).white().on_color(background).bold(),
+            alt.cost,
+            alt.cost,
+            alt.path.len(),
+            alt.distinct
+        );
+    }
+
+    println!("\nALTERNATIVE PATHS (ranked colours):");
+    println!("===================================");
+    let paths: Vec<&[(usize, usize)]> =
+        alternatives.iter().map(|alt| alt.path.as_slice()).collect();
+    print_colored_grid(grid, &paths, false, topology);
+}
+
+fn json_string(value: &str) -> String {
+    let mut out = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_coords(path: &[(usize, usize)]) -> String {
+    let coords: Vec<String> = path.iter().map(|&position| json_coord(position)).collect();
+    format!("[{}]", coords.join(", "))
+}
+
+fn json_result(result: &Result<PathResult, SolveError>, elapsed: Duration) -> String {
+    let result = match result {
+        Ok(result) => result,
+        Err(error) => {
+            let expanded = match error {
+                SolveError::NoPath { expanded } => expanded.to_string(),
+                _ => "null".to_string(),
+            };
+            return format!(
+                "{{\"found\": false, \"total_cost\": null, \"path_length\": 0, \"expanded\": {}, \"exact\": true, \"time_ms\": {:.3}, \"legs\": [], \"meetings\": [], \"path\": []}}",
+                expanded,
+                elapsed.as_secs_f64() * 1000.0
+            );
+        }
+    };
+    let path = &result.cells;
+    let legs: Vec<String> = result
+        .stats
+        .waypoints
+        .windows(2)
+        .zip(&result.leg_costs)
+        .map(|(leg, cost)| {
+            format!(
+                "{{\"from\": {}, \"to\": {}, \"cost\": {}}}",
+                json_coord(path[leg[0]]),
+                json_coord(path[leg[1]]),
+                cost
+            )
+        })
+        .collect();
+
+    format!(
+        "{{\"found\": true, \"total_cost\": {}, \"path_length\": {}, \"expanded\": {}, \"exact\": {}, \"time_ms\": {:.3}, \"legs\": [{}], \"meetings\": {}, \"path\": {}}}",
+        result.cost,
+        path.len(),
+        result.stats.expanded,
+        result.stats.exact,
+        elapsed.as_secs_f64() * 1000.0,
+        legs.join(", "),
+        json_coords(&result.stats.meetings),
+        json_coords(path)
+    )
+}
+
+struct Explorer {
+    grid: Grid,
+    opts: SearchOptions,
+    budget: LongestPathBudget,
+    cursor: (usize, usize),
+    start: (usize, usize),
+    goal: (usize, usize),
+    maximize: bool,
+    trace: Vec<(usize, usize)>,
+    path: Vec<(usize, usize)>,
+    cost: u64,
+    exact: bool,
+    step: usize,
+    playing: bool,
+    speed_ms: u64,
+    scroll: (usize, usize),
+}
+
+impl Explorer {
+    fn solve(&mut self) {
+        let mut trace = Vec::new();
+        let route = [self.start, self.goal];
+        let search_opts = SearchOptions {
+            maximize: self.maximize,
+            bidirectional: false,
+            ..self.opts.clone()
+        };
+        let mut result = Solver::new(&self.grid, search_opts, self.budget)
+            .and_then(|solver| solver.shortest_traced(&route, &mut trace));
+        if self.maximize {
+            result = Solver::new(&self.grid, self.opts.clone(), self.budget)
+                .and_then(|solver| solver.longest(&route));
+        }
+        match result {
+            Ok(result) => {
+                self.cost = result.cost;
+                self.exact = result.stats.exact;
+                self.path = result.cells;
+            }
+            Err(_) => self.path.clear(),
+        }
+        self.trace = trace
+            .into_iter()
+            .filter_map(|event| match event {
+                TraceEvent::Pop { position, .. } => Some(position),
+                _ => None,
+            })
+            .collect();
+        self.step = self.trace.len();
+        self.playing = false;
+    }
+
+    fn handle_key(&mut self, code: KeyCode) -> bool {
+        let (x, y) = self.cursor;
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Left | KeyCode::Char('h') => self.cursor.0 = x.saturating_sub(1),
+            KeyCode::Right | KeyCode::Char('l') => self.cursor.0 = (x + 1).min(self.grid.width - 1),
+            KeyCode::Up | KeyCode::Char('k') => self.cursor.1 = y.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => self.cursor.1 = (y + 1).min(self.grid.height - 1),
+            KeyCode::Char('s') if !self.grid.is_wall(x, y) => {
+                self.start = self.cursor;
+                self.solve();
+            }
+            KeyCode::Char('g') if !self.grid.is_wall(x, y) => {
+                self.goal = self.cursor;
+                self.solve();
+            }
+            KeyCode::Char('w') if self.cursor != self.start && self.cursor != self.goal => {
+                let idx = y * self.grid.width + x;
+                self.grid.walls[idx] = !self.grid.walls[idx];
+                self.solve();
+            }
+            KeyCode::Char('m') => {
+                self.maximize = !self.maximize;
+                self.solve();
+            }
+            KeyCode::Char(' ') => {
+                if self.step >= self.trace.len() {
+                    self.step = 0;
+                }
+                self.playing = !self.playing;
+            }
+            KeyCode::Char('n') | KeyCode::Char('.') => {
+                self.playing = false;
+                self.step = (self.step + 1).min(self.trace.len());
+            }
+            KeyCode::Char('b') | KeyCode::Char(',') => {
+                self.playing = false;
+                self.step = self.step.saturating_sub(1);
+            }
+            KeyCode::Home => self.step = 0,
+            KeyCode::End => self.step = self.trace.len(),
+            KeyCode::Char('+') => self.speed_ms = (self.speed_ms / 2).max(1),
+            KeyCode::Char('-') => self.speed_ms = (self.speed_ms * 2).min(2000),
+            _ => {}
+        }
+        true
+    }
+
+    fn draw(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let (cols, rows) = terminal::size()?;
+        let digits = self.grid.cell_width().digits();
+        let view_w = (cols as usize / (digits + 1)).max(1);
+        let view_h = (rows as usize).saturating_sub(4).max(1);
+        for (axis, pos, size) in [(0, self.cursor.0, view_w), (1, self.cursor.1, view_h)] {
+            let scroll = if axis == 0 {
+                &mut self.scroll.0
+            } else {
+                &mut self.scroll.1
+            };
+            if pos < *scroll {
+                *scroll = pos;
+            } else if pos >= *scroll + size {
+                *scroll = pos + 1 - size;
+            }
+        }
+
+        let visited: HashSet<(usize, usize)> = self.trace[..self.step].iter().copied().collect();
+        let current = self.step.checked_sub(1).map(|i| self.trace[i]);
+        let path: HashSet<(usize, usize)> = if self.step == self.trace.len() {
+            self.path.iter().copied().collect()
+        } else {
+            HashSet::new()
+        };
+
+        queue!(out, terminal::Clear(terminal::ClearType::All))?;
+        let y_end = (self.scroll.1 + view_h).min(self.grid.height);
+        let x_end = (self.scroll.0 + view_w).min(self.grid.width);
+        for (row, y) in (self.scroll.1..y_end).enumerate() {
+            queue!(out, cursor::MoveTo(0, row as u16))?;
+            for x in self.scroll.0..x_end {
+                let (r, g, b) = get_rgb(self.grid.shade(x, y));
+                let mut cell = ContentStyle::new();
+                let text = if self.grid.is_wall(x, y) {
+                    cell.foreground_color = Some(TermColor::DarkGrey);
+                    "X".repeat(digits)
+                } else if (x, y) == self.start || (x, y) == self.goal {
+                    cell.foreground_color = Some(TermColor::Black);
+                    cell.background_color = Some(TermColor::White);
+                    let label = if (x, y) == self.start { "S" } else { "G" };
+                    format!("{:>width$}", label, width = digits)
+                } else {
+                    cell.foreground_color = Some(TermColor::Rgb { r, g, b });
+                    self.grid.hex(x, y)
+                };
+                if path.contains(&(x, y)) {
+                    cell.foreground_color = Some(TermColor::White);
+                    cell.background_color = Some(TermColor::Black);
+                    cell.attributes.set(Attribute::Bold);
+                } else if Some((x, y)) == current {
+                    cell.background_color = Some(TermColor::Red);
+                } else if visited.contains(&(x, y)) {
+                    cell.background_color = Some(TermColor::DarkGrey);
+                }
+                if (x, y) == self.cursor {
+                    cell.attributes.set(Attribute::Reverse);
+                }
+                queue!(
+                    out,
+                    style::PrintStyledContent(cell.apply(text)),
+                    style::Print(" ")
+                )?;
+            }
+        }
+
+        let (cx, cy) = self.cursor;
+        let status = format!(
+            "cursor ({},{}) = {} | {} path: {} | step {}/{} | {} {}ms",
+            cx,
+            cy,
+            self.grid.hex(cx, cy),
+            if self.maximize { "MAX" } else { "MIN" },
+            if self.path.is_empty() {
+                "none".to_string()
+            } else {
+                format!(
+                    "cost {} ({}), {} cells",
+                    self.cost,
+                    if self.exact { "exact" } else { "approx" },
+                    self.path.len()
+                )
+            },
+            self.step,
+            self.trace.len(),
+            if self.playing { "playing" } else { "paused" },
+            self.speed_ms
+        );
+        queue!(
+            out,
+            cursor::MoveTo(0, (y_end - self.scroll.1 + 1) as u16),
+            style::Print(status),
+            cursor::MoveTo(0, (y_end - self.scroll.1 + 2) as u16),
+            style::SetForegroundColor(TermColor::DarkGrey),
+            style::Print(
+                "arrows/hjkl move  s start  g goal  w wall  m min/max  space play  n/b step  +/- speed  q quit"
+            ),
+            style::ResetColor
+        )?;
+        out.flush()
+    }
+}
+
+struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn run_tui(
+    grid: Grid,
+    start: (usize, usize),
+    goal: (usize, usize),
+    opts: SearchOptions,
+    budget: LongestPathBudget,
+) -> io::Result<()> {
+    let mut explorer = Explorer {
+        grid,
+        opts,
+        budget,
+        cursor: start,
+        start,
+        goal,
+        maximize: false,
+        trace: Vec::new(),
+        path: Vec::new(),
+        cost: 0,
+        exact: true,
+        step: 0,
+        playing: false,
+        speed_ms: 50,
+        scroll: (0, 0),
+    };
+    explorer.solve();
+
+    let mut out = io::stdout();
+    terminal::enable_raw_mode()?;
+    let _guard = TerminalGuard;
+    execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+    loop {
+        explorer.draw(&mut out)?;
+        let timeout = if explorer.playing {
+            Duration::from_millis(explorer.speed_ms)
+        } else {
+            Duration::from_secs(60)
+        };
+
+        if event::poll(timeout)? {
+            if let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+                && !explorer.handle_key(key.code)
+            {
+                return Ok(());
+            }
+        } else if explorer.playing {
+            explorer.step += 1;
+            if explorer.step >= explorer.trace.len() {
+                explorer.step = explorer.trace.len();
+                explorer.playing = false;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batch_patterns_and_csv_quoting() {
+        assert!(wildcard_match("*.txt", "map.txt"));
+        assert!(wildcard_match("m?p*", "map.bin"));
+        assert!(wildcard_match("*a*b", "xaab"));
+        assert!(!wildcard_match("*.txt", "map.bin"));
+        assert!(!wildcard_match("m?p", "mp"));
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("plain"), "plain");
+    }
 }